@@ -0,0 +1,314 @@
+use prettytable::{Cell, Row};
+use serde::Serialize;
+use crate::cli::{self, AliasCommand};
+use crate::config::{ConfigProblem, DkConfig};
+use crate::dkutil::*;
+use crate::output::*;
+
+/// An alias as listed by `dk alias ls`
+#[derive(Serialize, Default)]
+struct AliasInfo {
+    name: String,
+    command: String,
+    problem: String,
+}
+
+pub fn usage() {
+    cli::print_usage_section("ALIASES");
+}
+
+pub fn cmd(config: &DkConfig, command: Option<AliasCommand>) -> i32 {
+    match command {
+        None => show(config, OutputFormat::Table),
+        Some(AliasCommand::Ls { output }) => show(config, output),
+    }
+    0
+}
+
+fn show(config: &DkConfig, format: OutputFormat) {
+    let problems = problems(config);
+    let aliases: Vec<AliasInfo> = config
+        .aliases
+        .iter()
+        .map(|(name, command)| AliasInfo {
+            name: name.clone(),
+            command: command.clone(),
+            problem: problems
+                .iter()
+                .find(|p| p.path == format!("$.aliases.{}", name))
+                .map(|p| p.message.clone())
+                .unwrap_or_default(),
+        })
+        .collect();
+    if let Some(out) = render(&aliases, format) {
+        print!("{}", out);
+        return;
+    }
+    if aliases.is_empty() {
+        print_info("No alias in the configuration");
+        return;
+    }
+    let mut table = new_table(&["Alias", "Command"]);
+    for alias in &aliases {
+        table.add_row(Row::new(vec![Cell::new(&alias.name), Cell::new(&alias.command)]));
+    }
+    print_table(&table);
+    for problem in &problems {
+        print_warning(&problem.to_string());
+    }
+}
+
+/// Run the command line `args` (including the program name) whose command is an alias:
+/// each command of the expansion is given to `run_command`, the first failure stops the chain
+pub fn run(config: &DkConfig, args: &[String], run_command: &dyn Fn(&[String]) -> i32) -> i32 {
+    let Some(index) = args.get(1..).and_then(command_word).map(|i| i + 1) else {
+        return run_command(args);
+    };
+    let commands = match expand(config, &args[index], &args[index + 1..], &mut Vec::new()) {
+        Ok(commands) => commands,
+        Err(e) => {
+            print_error(&e);
+            return 1;
+        }
+    };
+    for command in &commands {
+        // les options globales placées avant l'alias s'appliquent à chaque commande
+        let line: Vec<String> = args[..index].iter().chain(command).cloned().collect();
+        if commands.len() > 1 {
+            print_info(&format!("dk {}", line[1..].join(" ")));
+        }
+        let code = run_command(&line);
+        if code != 0 {
+            return code;
+        }
+    }
+    0
+}
+
+/// Position of the command in a command line, after the global options
+pub fn command_word(words: &[String]) -> Option<usize> {
+    words.iter().position(|w| !w.starts_with('-'))
+}
+
+/// A word names an alias when it is defined and is not a dk command
+fn is_alias(config: &DkConfig, word: &str) -> bool {
+    config.aliases.contains_key(word) && !cli::command_names().iter().any(|c| c == word)
+}
+
+/// Commands of an alias definition, chained with `&&`
+fn parse_alias(definition: &str) -> Result<Vec<Vec<String>>, String> {
+    let words = split_shell_words(definition)?;
+    // `--filter=$@` n'a pas de sens : chaque argument doit rester un mot
+    if let Some(word) = words.iter().find(|w| w.contains("$@") && *w != "$@") {
+        return Err(format!("'{}': $@ must be a whole word", word));
+    }
+    let commands: Vec<Vec<String>> = words.split(|w| w == "&&").map(|c| c.to_vec()).collect();
+    if commands.iter().any(|c| c.is_empty()) {
+        return Err(String::from("empty command"));
+    }
+    Ok(commands)
+}
+
+/// Replace `$1`..`$9` and the `$@` words by the arguments; the arguments not used
+/// are appended to the last command, unless `$@` takes them all
+fn substitute(commands: &[Vec<String>], args: &[String]) -> Result<Vec<Vec<String>>, String> {
+    let mut used = 0;
+    let mut all = false;
+    let mut result = Vec::new();
+    for command in commands {
+        let mut words = Vec::new();
+        for word in command {
+            if word == "$@" {
+                all = true;
+                words.extend(args.iter().cloned());
+                continue;
+            }
+            let mut text = String::new();
+            let mut chars = word.chars().peekable();
+            while let Some(c) = chars.next() {
+                match (c, chars.peek().copied()) {
+                    ('$', Some(d @ '1'..='9')) => {
+                        chars.next();
+                        let index = d as usize - '0' as usize;
+                        let arg = args.get(index - 1).ok_or_else(|| format!("missing argument ${}", index))?;
+                        text.push_str(arg);
+                        used = used.max(index);
+                    }
+                    _ => text.push(c),
+                }
+            }
+            words.push(text);
+        }
+        result.push(words);
+    }
+    if !all && let Some(last) = result.last_mut() {
+        last.extend(args.iter().skip(used).cloned());
+    }
+    Ok(result)
+}
+
+/// Expand the alias `name` called with `args` into dk command lines (without the program name);
+/// `stack` holds the aliases being expanded, to detect a recursion
+pub fn expand(config: &DkConfig, name: &str, args: &[String], stack: &mut Vec<String>) -> Result<Vec<Vec<String>>, String> {
+    stack.push(name.to_string());
+    if stack[..stack.len() - 1].iter().any(|a| a == name) {
+        return Err(format!("Alias recursion: {}", stack.join(" -> ")));
+    }
+    let definition = config.aliases.get(name).ok_or_else(|| format!("Unknown alias '{}'", name))?;
+    let commands = parse_alias(definition)
+        .and_then(|commands| substitute(&commands, args))
+        .map_err(|e| format!("Alias '{}': {}", name, e))?;
+
+    let mut expanded = Vec::new();
+    for command in commands {
+        match command_word(&command) {
+            Some(i) if is_alias(config, &command[i]) => {
+                for inner in expand(config, &command[i], &command[i + 1..], stack)? {
+                    expanded.push(command[..i].iter().chain(&inner).cloned().collect());
+                }
+            }
+            _ => expanded.push(command),
+        }
+    }
+    stack.pop();
+    Ok(expanded)
+}
+
+/// The aliases reached from `name`, up to the first one seen twice
+fn cycle(config: &DkConfig, name: &str, stack: &mut Vec<String>) -> Option<Vec<String>> {
+    if let Some(start) = stack.iter().position(|a| a == name) {
+        let mut cycle = stack[start..].to_vec();
+        cycle.push(name.to_string());
+        return Some(cycle);
+    }
+    stack.push(name.to_string());
+    let commands = config.aliases.get(name).and_then(|d| parse_alias(d).ok()).unwrap_or_default();
+    for command in commands {
+        if let Some(word) = command_word(&command).map(|i| &command[i]).filter(|w| is_alias(config, w))
+            && let Some(cycle) = cycle(config, word, stack)
+        {
+            return Some(cycle);
+        }
+    }
+    stack.pop();
+    None
+}
+
+/// Problems of the aliases: a dk command replaced, a definition that does not parse, a recursion
+pub fn problems(config: &DkConfig) -> Vec<ConfigProblem> {
+    let commands = cli::command_names();
+    let mut problems = Vec::new();
+    for (name, definition) in &config.aliases {
+        let message = if commands.contains(name) {
+            Some(format!("'{}' is a dk command, the alias is never used", name))
+        } else if let Err(e) = parse_alias(definition) {
+            Some(e)
+        } else {
+            // un cycle est signalé une seule fois, sur le premier alias qui en fait partie
+            cycle(config, name, &mut Vec::new())
+                .filter(|c| c[0] == *name && c.iter().min() == Some(name))
+                .map(|c| format!("recursion {}", c.join(" -> ")))
+        };
+        if let Some(message) = message {
+            problems.push(ConfigProblem { path: format!("$.aliases.{}", name), message });
+        }
+    }
+    problems
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    fn config(aliases: &[(&str, &str)]) -> DkConfig {
+        let mut config = DkConfig::default_config();
+        config.aliases = aliases.iter().map(|(n, d)| (n.to_string(), d.to_string())).collect();
+        config
+    }
+
+    fn words(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    fn lines(commands: &[Vec<String>]) -> Vec<String> {
+        commands.iter().map(|c| c.join(" ")).collect()
+    }
+
+    #[test]
+    fn test_expand() {
+        let config = config(&[
+            ("clean", "sys prune && vol prune"),
+            ("sql", "ots up sqlserver --wait"),
+            ("up", "ots up $1 --wait --timeout $2"),
+            ("logs", "ots logs \"$@\" && ps"),
+            ("all", "clean && sql"),
+        ]);
+        let expand = |name: &str, args: &str| expand(&config, name, &words(args), &mut Vec::new()).map(|c| lines(&c));
+
+        assert_eq!(expand("clean", "").unwrap(), vec!["sys prune", "vol prune"]);
+        assert_eq!(expand("sql", "--timeout 2m").unwrap(), vec!["ots up sqlserver --wait --timeout 2m"]);
+        assert_eq!(expand("up", "kroki 30s").unwrap(), vec!["ots up kroki --wait --timeout 30s"]);
+        assert_eq!(expand("up", "kroki 30s db").unwrap(), vec!["ots up kroki --wait --timeout 30s db"]);
+        assert_eq!(expand("up", "kroki").unwrap_err(), "Alias 'up': missing argument $2");
+        assert_eq!(expand("logs", "db -f").unwrap(), vec!["ots logs db -f", "ps"]);
+        assert_eq!(expand("all", "").unwrap(), vec!["sys prune", "vol prune", "ots up sqlserver --wait"]);
+    }
+
+    #[test]
+    fn test_recursion_and_shadowing() {
+        let config = config(&[
+            ("a", "ps && b"),
+            ("b", "--no-color c"),
+            ("c", "a"),
+            ("d", "c"),
+            ("ps", "ps -o json"),
+            ("bad", "ps && && im"),
+            ("quote", "ots up 'kroki"),
+            ("filter", "ps --filter=$@"),
+        ]);
+        assert_eq!(expand(&config, "d", &[], &mut Vec::new()).unwrap_err(), "Alias recursion: d -> c -> a -> b -> c");
+        assert_eq!(
+            expand(&config, "filter", &words("name=db"), &mut Vec::new()).unwrap_err(),
+            "Alias 'filter': '--filter=$@': $@ must be a whole word"
+        );
+        let problems: Vec<String> = problems(&config).iter().map(|p| p.to_string()).collect();
+        assert_eq!(
+            problems,
+            vec![
+                "$.aliases.a: recursion a -> b -> c -> a",
+                "$.aliases.bad: empty command",
+                "$.aliases.filter: '--filter=$@': $@ must be a whole word",
+                "$.aliases.ps: 'ps' is a dk command, the alias is never used",
+                "$.aliases.quote: Unterminated quote in: ots up 'kroki",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_run() {
+        let config = config(&[("clean", "sys prune && vol prune"), ("sql", "ots up sqlserver")]);
+        let calls: RefCell<Vec<String>> = RefCell::new(Vec::new());
+        let calls = &calls;
+        let codes = |failing: &'static str| {
+            move |line: &[String]| {
+                calls.borrow_mut().push(line.join(" "));
+                if line.join(" ").contains(failing) { 1 } else { 0 }
+            }
+        };
+
+        assert_eq!(run(&config, &words("dk --no-color clean"), &codes("none")), 0);
+        assert_eq!(run(&config, &words("dk clean"), &codes("sys")), 1);
+        assert_eq!(run(&config, &words("dk sql --wait"), &codes("none")), 0);
+        assert_eq!(
+            calls.borrow().clone(),
+            vec![
+                "dk --no-color sys prune",
+                "dk --no-color vol prune",
+                "dk sys prune",
+                "dk ots up sqlserver --wait",
+            ]
+        );
+    }
+}
@@ -0,0 +1,539 @@
+use clap::{Arg, ArgAction, Args, Command, CommandFactory, Parser, Subcommand};
+use colored::*;
+use std::time::Duration;
+use crate::completion::CompletionShell;
+use crate::config_format::ConfigFormat;
+use crate::dkutil::*;
+use crate::image_archive::ArchiveFormat;
+use crate::output::OutputFormat;
+
+/// dk - handle Docker images, containers, volumes and OTS services efficiently
+#[derive(Parser, Debug)]
+#[command(name = "dk", version, disable_help_subcommand = true)]
+pub struct Cli {
+    /// Disable the colors (also with the NO_COLOR environment variable)
+    #[arg(long, global = true)]
+    pub no_color: bool,
+
+    #[command(subcommand)]
+    pub command: Option<Commands>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Commands {
+    /// Show state of the containers
+    Ps {
+        #[arg(short, long, value_enum, default_value = "table", value_name = "format")]
+        output: OutputFormat,
+    },
+    /// Remove container(s)
+    Rm {
+        #[arg(required = true, value_name = "container")]
+        containers: Vec<String>,
+    },
+    /// Run a bash shell into the container
+    Shell {
+        #[arg(value_name = "container")]
+        container: String,
+    },
+    /// Truncate the log file of the container(s)
+    Trunclog {
+        /// Keep the end of the log (e.g. 512k, 10M)
+        #[arg(long, value_name = "size", value_parser = parse_size_arg)]
+        keep: Option<u64>,
+        #[arg(required = true, value_name = "container")]
+        containers: Vec<String>,
+    },
+    /// Show the list of images
+    Im {
+        #[arg(short, long, value_enum, default_value = "table", value_name = "format")]
+        output: OutputFormat,
+        #[command(subcommand)]
+        command: Option<ImCommand>,
+    },
+    /// Show the list of volumes
+    Vol {
+        #[arg(short, long, value_enum, default_value = "table", value_name = "format")]
+        output: OutputFormat,
+        #[command(subcommand)]
+        command: Option<VolCommand>,
+    },
+    /// Show extended information
+    Sys {
+        #[command(subcommand)]
+        command: Option<SysCommand>,
+    },
+    /// Show the OTS commands and the supported services
+    Ots {
+        #[command(subcommand)]
+        command: Option<OtsCommand>,
+    },
+    /// Show the configuration commands
+    Config {
+        #[command(subcommand)]
+        command: Option<ConfigCommand>,
+    },
+    /// List the aliases of the configuration
+    Alias {
+        #[command(subcommand)]
+        command: Option<AliasCommand>,
+    },
+    /// Print the completion script of a shell
+    Completions {
+        #[arg(value_enum, value_name = "shell")]
+        shell: CompletionShell,
+    },
+    /// Complete a partial command line (called by the completion scripts)
+    #[command(name = "__complete", hide = true)]
+    Complete {
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true, value_name = "word")]
+        words: Vec<String>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ImCommand {
+    /// Delete the specified images
+    Rm {
+        #[arg(required = true, value_name = "image")]
+        images: Vec<String>,
+    },
+    /// Save the specified images into compressed archives
+    Save {
+        #[arg(long, value_enum, default_value = "gzip", value_name = "format")]
+        format: ArchiveFormat,
+        #[arg(required = true, value_name = "image")]
+        images: Vec<String>,
+    },
+    /// Load the specified image files
+    Load {
+        #[arg(required = true, value_name = "file")]
+        files: Vec<String>,
+    },
+    /// Scan images for vulnerabilities
+    Scan {
+        #[arg(required = true, value_name = "image")]
+        images: Vec<String>,
+    },
+    /// Image archives
+    #[command(subcommand_required = true)]
+    Archive {
+        #[command(subcommand)]
+        command: ArchiveCommand,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ArchiveCommand {
+    /// List the image archives of a directory
+    Ls {
+        #[arg(default_value = ".", value_name = "dir")]
+        dir: String,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum VolCommand {
+    /// Delete all unused volumes
+    Prune,
+    /// Delete specified volumes
+    Rm {
+        #[arg(required = true, value_name = "volume")]
+        volumes: Vec<String>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum SysCommand {
+    /// Show extended information
+    Show,
+    /// Delete unused data (containers, images, volumes, build cache)
+    Prune,
+    /// Show data size (docker system df)
+    Size,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum OtsCommand {
+    /// List the services with the state of their container
+    #[command(visible_alias = "status")]
+    Ls {
+        #[arg(short, long, value_enum, default_value = "table", value_name = "format")]
+        output: OutputFormat,
+    },
+    /// Check the configuration of the services
+    Check,
+    /// Add the services of a docker compose file to the configuration
+    Import {
+        #[arg(value_name = "file")]
+        file: String,
+    },
+    /// Print a docker compose file of the services (all by default)
+    Export {
+        #[arg(value_name = "ots")]
+        names: Vec<String>,
+    },
+    /// Add a service to the configuration, from options or from an existing container
+    Add(Box<OtsAddArgs>),
+    /// Edit the configuration with $EDITOR, validated before being saved
+    Edit,
+    /// Remove services from the configuration (their container is kept)
+    Remove {
+        #[arg(required = true, value_name = "ots")]
+        names: Vec<String>,
+    },
+    /// Rename a service, and its container when it follows the ots_<name> convention
+    Rename {
+        #[arg(value_name = "ots")]
+        old: String,
+        #[arg(value_name = "name")]
+        new: String,
+    },
+    /// Show the definition of a service, its variables replaced and its secrets masked
+    Show {
+        #[arg(value_name = "ots")]
+        name: String,
+    },
+    /// Create and run the container of the specified services
+    Up {
+        /// Wait until the services are ready
+        #[arg(long)]
+        wait: bool,
+        /// Maximum time to wait (e.g. 90s, 2m)
+        #[arg(long, requires = "wait", default_value = "60s", value_name = "duration", value_parser = parse_duration_arg)]
+        timeout: Duration,
+        #[arg(required = true, value_name = "ots")]
+        names: Vec<String>,
+    },
+    /// Delete the container of the specified services
+    Down {
+        /// Also delete the containers of the services they depend on
+        #[arg(long)]
+        with_deps: bool,
+        #[arg(required = true, value_name = "ots")]
+        names: Vec<String>,
+    },
+    /// Start again the stopped container of the specified services
+    Start {
+        #[arg(required = true, value_name = "ots")]
+        names: Vec<String>,
+    },
+    /// Stop the container of the specified services, without removing it
+    Stop {
+        /// Also stop the services they depend on
+        #[arg(long)]
+        with_deps: bool,
+        #[arg(required = true, value_name = "ots")]
+        names: Vec<String>,
+    },
+    /// Restart the container of the specified services
+    Restart {
+        #[arg(required = true, value_name = "ots")]
+        names: Vec<String>,
+    },
+    /// Pull the image of the services and recreate their container when it changed
+    Upgrade {
+        /// Upgrade every service having a container
+        #[arg(long, conflicts_with = "names")]
+        all: bool,
+        #[arg(required_unless_present = "all", value_name = "ots")]
+        names: Vec<String>,
+    },
+    /// Follow the logs of the service
+    Logs {
+        /// Number of lines to show from the end of the logs
+        #[arg(long, default_value_t = 100, value_name = "lines")]
+        tail: u32,
+        #[arg(value_name = "ots")]
+        name: String,
+    },
+    /// Run a bash shell into the container of the service
+    Shell {
+        #[arg(value_name = "ots")]
+        name: String,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ConfigCommand {
+    /// Write the default configuration file and its JSON Schema
+    Init {
+        /// Replace an existing file (kept as a .bak backup)
+        #[arg(long)]
+        force: bool,
+    },
+    /// Print the JSON Schema of the configuration file
+    Schema,
+    /// Upgrade a configuration file (the global one by default) to the current version
+    Migrate {
+        #[arg(value_name = "file")]
+        file: Option<String>,
+    },
+    /// Convert a configuration file (the global one by default) to another format
+    Convert {
+        #[arg(long, value_enum, value_name = "format")]
+        to: ConfigFormat,
+        #[arg(value_name = "file")]
+        file: Option<String>,
+    },
+    /// Print the configuration merged from the global file, the project file and the DK_* variables
+    Show {
+        /// Show the file or the variable each value comes from
+        #[arg(long)]
+        origin: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum AliasCommand {
+    /// List the aliases and their problems
+    Ls {
+        #[arg(short, long, value_enum, default_value = "table", value_name = "format")]
+        output: OutputFormat,
+    },
+}
+
+/// Definition of a service added by `dk ots add`
+#[derive(Args, Debug, Default)]
+pub struct OtsAddArgs {
+    #[arg(value_name = "name")]
+    pub name: String,
+    /// Image of the service
+    #[arg(long, required_unless_present = "from", conflicts_with = "from", value_name = "image")]
+    pub image: Option<String>,
+    /// Copy the definition of an existing container
+    #[arg(long, value_name = "container")]
+    pub from: Option<String>,
+    /// Name of the container (ots_<name> by default)
+    #[arg(long, value_name = "name")]
+    pub container_name: Option<String>,
+    /// Published port (host:container)
+    #[arg(short = 'p', long = "publish", value_name = "port")]
+    pub ports: Vec<String>,
+    /// Environment variable (KEY=value)
+    #[arg(short = 'e', long = "env", value_name = "KEY=value")]
+    pub env: Vec<String>,
+    /// Volume or bind mount (as docker run -v)
+    #[arg(short = 'v', long = "volume", value_name = "volume")]
+    pub volumes: Vec<String>,
+    /// Mount (as docker run --mount)
+    #[arg(long = "mount", value_name = "mount")]
+    pub mounts: Vec<String>,
+    /// Restart policy
+    #[arg(long, value_name = "policy")]
+    pub restart: Option<String>,
+    /// Service started before this one
+    #[arg(long = "depends-on", value_name = "ots")]
+    pub depends_on: Vec<String>,
+    /// Command given to the image
+    #[arg(last = true, value_name = "command")]
+    pub command: Vec<String>,
+}
+
+fn parse_size_arg(s: &str) -> Result<u64, String> {
+    parse_size(s).ok_or_else(|| format!("invalid size '{}' (e.g. 512k, 10M)", s))
+}
+
+fn parse_duration_arg(s: &str) -> Result<Duration, String> {
+    parse_duration(s).ok_or_else(|| format!("invalid duration '{}' (e.g. 90s, 2m)", s))
+}
+
+/// Sections of the usage and the top level commands they describe
+const SECTIONS: &[(&str, &[&str])] = &[
+    ("CONTAINERS", &["ps", "rm", "shell", "trunclog"]),
+    ("IMAGES", &["im"]),
+    ("VOLUMES", &["vol"]),
+    ("SYSTEM", &["sys"]),
+    ("OTS", &["ots"]),
+    ("CONFIG", &["config"]),
+    ("ALIASES", &["alias"]),
+    ("SHELL", &["completions"]),
+];
+
+const USAGE_WIDTH: usize = 44;
+
+/// Names of the top level commands, which an alias cannot replace
+pub fn command_names() -> Vec<String> {
+    Cli::command()
+        .get_subcommands()
+        .flat_map(|c| std::iter::once(c.get_name()).chain(c.get_all_aliases()))
+        .map(String::from)
+        .collect()
+}
+
+/// Print a section of the usage, generated from the command line definition
+pub fn print_usage_section(section: &str) {
+    println!("{}", format!("{}:", section).cyan());
+    let root = Cli::command();
+    let commands = SECTIONS
+        .iter()
+        .find(|(name, _)| *name == section)
+        .map(|(_, commands)| *commands)
+        .unwrap_or_default();
+    for name in commands {
+        if let Some(command) = root.find_subcommand(name) {
+            print_usage_lines(command, "dk");
+        }
+    }
+}
+
+fn print_usage_lines(command: &Command, parent: &str) {
+    let path = format!("{} {}", parent, command.get_name());
+    let runs_alone = !command.has_subcommands() || !command.is_subcommand_required_set();
+    if runs_alone {
+        let args = usage_args(command);
+        let width = path.len() + 1 + args.len();
+        let padding = " ".repeat(USAGE_WIDTH.saturating_sub(width));
+        let about = command.get_about().map(|a| a.to_string()).unwrap_or_default();
+        print_colored(&format!("(y) . {} (b){}{} (w): {}", path, args, padding, about));
+    }
+    for sub in command.get_subcommands() {
+        print_usage_lines(sub, &path);
+    }
+}
+
+/// Most options shown one by one in the usage, beyond that they become `[options]`
+const USAGE_MAX_OPTIONS: usize = 3;
+
+/// Short description of the arguments of a command (e.g. `[--keep <size>] <container*>`)
+fn usage_args(command: &Command) -> String {
+    let args: Vec<&Arg> = command
+        .get_arguments()
+        .filter(|a| !a.is_global_set() && !matches!(a.get_action(), ArgAction::Help | ArgAction::Version))
+        .collect();
+    let collapse = args.iter().filter(|a| !a.is_positional()).count() > USAGE_MAX_OPTIONS;
+    let mut parts: Vec<String> = Vec::new();
+    for arg in args {
+        if !collapse || arg.is_positional() {
+            parts.push(usage_arg(arg));
+        } else if !parts.iter().any(|p| p == "[options]") {
+            parts.push(String::from("[options]"));
+        }
+    }
+    parts.join(" ")
+}
+
+fn usage_arg(arg: &Arg) -> String {
+    let value = arg
+        .get_value_names()
+        .and_then(|v| v.first())
+        .map(|v| v.to_string())
+        .unwrap_or_else(|| arg.get_id().to_string());
+    if arg.is_positional() {
+        let multiple = matches!(arg.get_action(), ArgAction::Append);
+        let name = if multiple { format!("{}*", value) } else { value };
+        if arg.is_required_set() {
+            format!("<{}>", name)
+        } else {
+            format!("[{}]", name)
+        }
+    } else {
+        let flag = match (arg.get_short(), arg.get_long()) {
+            (Some(s), _) => format!("-{}", s),
+            (None, Some(l)) => format!("--{}", l),
+            (None, None) => String::new(),
+        };
+        if matches!(arg.get_action(), ArgAction::SetTrue) {
+            format!("[{}]", flag)
+        } else {
+            format!("[{} <{}>]", flag, value)
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(line: &str) -> Result<Cli, clap::Error> {
+        Cli::try_parse_from(line.split_whitespace())
+    }
+
+    #[test]
+    fn test_definition() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn test_parse() {
+        let cli = parse("dk trunclog --keep 10M 1 web").unwrap();
+        match cli.command {
+            Some(Commands::Trunclog { keep, containers }) => {
+                assert_eq!(keep, Some(10 * 1024 * 1024));
+                assert_eq!(containers, vec!["1", "web"]);
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+
+        let cli = parse("dk im save --format zstd 3 --no-color").unwrap();
+        assert!(cli.no_color);
+        assert!(matches!(
+            cli.command,
+            Some(Commands::Im { command: Some(ImCommand::Save { format: ArchiveFormat::Zstd, .. }), .. })
+        ));
+
+        let cli = parse("dk ots up --wait sqlserver").unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Commands::Ots { command: Some(OtsCommand::Up { wait: true, timeout, .. }) })
+                if timeout == Duration::from_secs(60)
+        ));
+
+        let cli = parse("dk ots add web --image nginx:1.27 -p 8080:80 -e A=1 -e B=2 -- nginx -g daemon").unwrap();
+        match cli.command {
+            Some(Commands::Ots { command: Some(OtsCommand::Add(args)) }) => {
+                assert_eq!(args.image.as_deref(), Some("nginx:1.27"));
+                assert_eq!(args.ports, vec!["8080:80"]);
+                assert_eq!(args.env, vec!["A=1", "B=2"]);
+                assert_eq!(args.command, vec!["nginx", "-g", "daemon"]);
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+
+        assert!(matches!(
+            parse("dk ots down --with-deps app").unwrap().command,
+            Some(Commands::Ots { command: Some(OtsCommand::Down { with_deps: true, .. }) })
+        ));
+
+        let cli = parse("dk vol -o csv").unwrap();
+        assert!(matches!(cli.command, Some(Commands::Vol { output: OutputFormat::Csv, command: None })));
+        assert!(parse("dk").unwrap().command.is_none());
+        assert!(matches!(
+            parse("dk alias ls -o json").unwrap().command,
+            Some(Commands::Alias { command: Some(AliasCommand::Ls { output: OutputFormat::Json }) })
+        ));
+        assert!(command_names().contains(&String::from("ots")));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(parse("dk ps web").is_err());
+        assert!(parse("dk rm").is_err());
+        assert!(parse("dk trunclog --keep lots web").is_err());
+        assert!(parse("dk im rm --force 3").is_err());
+        assert!(parse("dk unknown").is_err());
+        assert!(parse("dk ots upgrade").is_err());
+        assert!(parse("dk ots up --timeout 10s kroki").is_err());
+        assert!(parse("dk ots up --wait --timeout soon kroki").is_err());
+        assert!(parse("dk ots upgrade --all kroki").is_err());
+        assert!(parse("dk ots add web").is_err());
+        assert!(parse("dk ots add web --image nginx --from ots_web").is_err());
+        assert_eq!(parse("dk ps --help").unwrap_err().exit_code(), 0);
+        let version = parse("dk --version").unwrap_err().render().to_string();
+        assert_eq!(version.trim(), format!("dk {}", env!("CARGO_PKG_VERSION")));
+    }
+
+    #[test]
+    fn test_usage_args() {
+        let root = Cli::command();
+        let trunclog = root.find_subcommand("trunclog").unwrap();
+        assert_eq!(usage_args(trunclog), "[--keep <size>] <container*>");
+        let im = root.find_subcommand("im").unwrap();
+        assert_eq!(usage_args(im), "[-o <format>]");
+        let ls = im.find_subcommand("archive").unwrap().find_subcommand("ls").unwrap();
+        assert_eq!(usage_args(ls), "[dir]");
+        let add = root.find_subcommand("ots").unwrap().find_subcommand("add").unwrap();
+        assert_eq!(usage_args(add), "<name> [options] [command*]");
+    }
+}
@@ -1,22 +1,59 @@
-use std::{process::Command};
+use std::io::{self, Write};
+use std::process::{Command, ExitStatus, Stdio};
+use crate::engine_api::EngineClient;
 
 
 
 // a trait to abstract the command execution
 pub trait CommandExecutor {
+    /// Run the command and capture its standard output
     fn execute(&self, command: &[&str]) -> Result<String, String>;
+
+    /// Run the command attached to the terminal and return its exit code
+    fn run(&self, command: &[&str]) -> Result<i32, String>;
+
+    /// Run the command, stream its standard output into `out` and return its exit code
+    fn stream(&self, command: &[&str], out: &mut dyn Write) -> Result<i32, String>;
+
+    /// Docker Engine API client to use for the listings instead of the CLI
+    fn engine(&self) -> Option<&EngineClient> {
+        None
+    }
 }
 
 // real implementation that call the external command
 pub struct RealCommandExecutor;
 
+impl RealCommandExecutor {
+    fn command(command: &[&str]) -> Command {
+        let mut cmd = Command::new(command[0]);
+        cmd.args(&command[1..]);
+        cmd
+    }
+
+    fn spawn_error(command: &[&str], e: io::Error) -> String {
+        format!("Unable to run '{}': {}", command[0], e)
+    }
+
+    /// Exit code of a process, `128 + signal` (like a shell) when it was killed
+    fn exit_code(status: ExitStatus) -> i32 {
+        #[cfg(unix)]
+        {
+            use std::os::unix::process::ExitStatusExt;
+            if let Some(signal) = status.signal() {
+                return 128 + signal;
+            }
+        }
+        status.code().unwrap_or(1)
+    }
+}
+
 impl CommandExecutor for RealCommandExecutor {
 
     fn execute(&self, command: &[&str]) -> Result<String, String> {
-        let output = Command::new(command[0])
-            .args(&command[1..])
+        let output = Self::command(command)
             .output()
-            .map_err(|e| e.to_string())?;
+            .map_err(|e| Self::spawn_error(command, e))?;
         
         if output.status.success() {
             Ok(String::from_utf8_lossy(&output.stdout).to_string())
@@ -24,31 +61,175 @@ impl CommandExecutor for RealCommandExecutor {
             Err(String::from_utf8_lossy(&output.stderr).to_string())
         }
     }
+
+    fn run(&self, command: &[&str]) -> Result<i32, String> {
+        let status = Self::command(command)
+            .status()
+            .map_err(|e| Self::spawn_error(command, e))?;
+        Ok(Self::exit_code(status))
+    }
+
+    fn stream(&self, command: &[&str], out: &mut dyn Write) -> Result<i32, String> {
+        let mut child = Self::command(command)
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|e| Self::spawn_error(command, e))?;
+        let mut stdout = child.stdout.take().expect("stdout is piped");
+        let copied = io::copy(&mut stdout, out);
+        let status = child.wait().map_err(|e| e.to_string())?;
+        copied.map_err(|e| e.to_string())?;
+        Ok(Self::exit_code(status))
+    }
     
 }
 
-// // mock for tests
-// pub struct MockCommandExecutor {
-//     responses: HashMap<String, Result<String, String>>,
-// }
-
-// impl MockCommandExecutor {
-//     pub fn new() -> Self {
-//         Self {
-//             responses: HashMap::new(),
-//         }
-//     }
-    
-//     pub fn expect(&mut self, command: &[&str], response: Result<String, String>) {
-//         self.responses.insert(command.join(" "), response);
-//     }
-// }
-
-// impl CommandExecutor for MockCommandExecutor {
-//     fn execute(&self, command: &[&str]) -> Result<String, String> {
-//         let cmd_str = command.join(" ");
-//         self.responses.get(&cmd_str)
-//             .cloned()
-//             .unwrap_or_else(|| panic!("Unexpected command: {}", cmd_str))
-//     }
-// }
+#[cfg(test)]
+pub use mock::*;
+
+#[cfg(test)]
+mod mock {
+    use super::CommandExecutor;
+    use std::cell::RefCell;
+    use std::fs;
+    use std::io::Write;
+
+    /// Canned answer of the mock for a command
+    #[derive(Clone, Debug)]
+    pub struct MockResponse {
+        pub stdout: String,
+        pub stderr: String,
+        pub code: i32,
+    }
+
+    impl MockResponse {
+        pub fn ok(stdout: &str) -> Self {
+            Self { stdout: stdout.to_string(), stderr: String::new(), code: 0 }
+        }
+
+        pub fn fail(code: i32, stderr: &str) -> Self {
+            Self { stdout: String::new(), stderr: stderr.to_string(), code }
+        }
+
+        /// Answer with the content of a file of `tests/fixtures`
+        /// (output captured from a real docker)
+        pub fn fixture(name: &str) -> Self {
+            let path = format!("{}/tests/fixtures/{}", env!("CARGO_MANIFEST_DIR"), name);
+            let stdout = fs::read_to_string(&path)
+                .unwrap_or_else(|e| panic!("Unable to read fixture {}: {}", path, e));
+            Self::ok(&stdout)
+        }
+    }
+
+    /// Scripted executor for tests.
+    ///
+    /// Each expectation is an argv pattern where `*` matches any single
+    /// argument and a trailing `...` matches the remaining ones. The first
+    /// matching expectation gives the answer, every call is recorded and an
+    /// unexpected command makes the test panic.
+    #[derive(Default)]
+    pub struct MockCommandExecutor {
+        expectations: Vec<(Vec<String>, MockResponse)>,
+        calls: RefCell<Vec<Vec<String>>>,
+    }
+
+    impl MockCommandExecutor {
+        pub fn new() -> Self {
+            Self::default()
+        }
+
+        pub fn expect(&mut self, pattern: &[&str], response: MockResponse) -> &mut Self {
+            let pattern = pattern.iter().map(|s| s.to_string()).collect();
+            self.expectations.push((pattern, response));
+            self
+        }
+
+        /// Commands received so far
+        pub fn calls(&self) -> Vec<Vec<String>> {
+            self.calls.borrow().clone()
+        }
+
+        /// Commands received so far, as strings
+        pub fn command_lines(&self) -> Vec<String> {
+            self.calls().iter().map(|c| c.join(" ")).collect()
+        }
+
+        fn matches(pattern: &[String], command: &[&str]) -> bool {
+            if pattern.last().map(|p| p.as_str()) == Some("...") {
+                let fixed = &pattern[..pattern.len() - 1];
+                return command.len() >= fixed.len() && Self::matches(fixed, &command[..fixed.len()]);
+            }
+            pattern.len() == command.len()
+                && pattern.iter().zip(command).all(|(p, c)| p == "*" || p == c)
+        }
+
+        fn respond(&self, command: &[&str]) -> MockResponse {
+            self.calls.borrow_mut().push(command.iter().map(|s| s.to_string()).collect());
+            self.expectations
+                .iter()
+                .find(|(pattern, _)| Self::matches(pattern, command))
+                .map(|(_, response)| response.clone())
+                .unwrap_or_else(|| panic!("Unexpected command: {}", command.join(" ")))
+        }
+    }
+
+    impl CommandExecutor for MockCommandExecutor {
+        fn execute(&self, command: &[&str]) -> Result<String, String> {
+            let response = self.respond(command);
+            if response.code == 0 {
+                Ok(response.stdout)
+            } else {
+                Err(response.stderr)
+            }
+        }
+
+        fn run(&self, command: &[&str]) -> Result<i32, String> {
+            let response = self.respond(command);
+            print!("{}", response.stdout);
+            Ok(response.code)
+        }
+
+        fn stream(&self, command: &[&str], out: &mut dyn Write) -> Result<i32, String> {
+            let response = self.respond(command);
+            out.write_all(response.stdout.as_bytes()).map_err(|e| e.to_string())?;
+            Ok(response.code)
+        }
+    }
+
+
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_patterns() {
+            let mut mock = MockCommandExecutor::new();
+            mock.expect(&["docker", "inspect", "*"], MockResponse::ok("one"))
+                .expect(&["docker", "run", "..."], MockResponse::fail(125, "boom"));
+
+            assert_eq!(mock.execute(&["docker", "inspect", "abc"]), Ok(String::from("one")));
+            assert_eq!(mock.execute(&["docker", "run", "-d", "nginx"]), Err(String::from("boom")));
+            assert_eq!(mock.run(&["docker", "run"]), Ok(125));
+            assert_eq!(
+                mock.command_lines(),
+                vec!["docker inspect abc", "docker run -d nginx", "docker run"]
+            );
+        }
+
+        #[test]
+        #[cfg(unix)]
+        fn test_killed_process_fails() {
+            use crate::command_executor::RealCommandExecutor;
+            let executor = RealCommandExecutor;
+            assert_eq!(executor.run(&["sh", "-c", "exit 3"]), Ok(3));
+            // un docker save tué en cours de route ne donne pas une archive valide
+            assert_eq!(executor.run(&["sh", "-c", "kill -TERM $$"]), Ok(128 + 15));
+            assert_eq!(executor.stream(&["sh", "-c", "echo part; kill -KILL $$"], &mut Vec::new()), Ok(128 + 9));
+        }
+
+        #[test]
+        #[should_panic(expected = "Unexpected command: docker inspect a b")]
+        fn test_unexpected_command() {
+            let mock = MockCommandExecutor::new();
+            let _ = mock.execute(&["docker", "inspect", "a", "b"]);
+        }
+    }
+}
@@ -0,0 +1,269 @@
+use clap::{Arg, ArgAction, Command, CommandFactory, ValueEnum};
+use crate::cli::{self, Cli};
+use crate::command_executor::*;
+use crate::config::DkConfig;
+use crate::container_helper;
+use crate::image_helper;
+use crate::volume_helper;
+
+/// Shells supported by `dk completions`
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum CompletionShell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+const BASH_SCRIPT: &str = r#"# dk completion for bash : source <(dk completions bash)
+_dk() {
+    local cur words cword
+    if declare -F _get_comp_words_by_ref >/dev/null; then
+        _get_comp_words_by_ref -n : cur words cword
+    else
+        cur="${COMP_WORDS[COMP_CWORD]}"
+        words=("${COMP_WORDS[@]}")
+        cword=$COMP_CWORD
+    fi
+    local IFS=$'\n'
+    COMPREPLY=($(dk __complete -- "${words[@]:1:cword}" 2>/dev/null))
+    if declare -F __ltrim_colon_completions >/dev/null; then
+        __ltrim_colon_completions "$cur"
+    fi
+}
+complete -o default -F _dk dk
+"#;
+
+const ZSH_SCRIPT: &str = r#"#compdef dk
+# dk completion for zsh : source <(dk completions zsh)
+_dk() {
+    local -a candidates
+    candidates=(${(f)"$(dk __complete -- "${(@)words[2,CURRENT]}" 2>/dev/null)"})
+    if (( ${#candidates} )); then
+        compadd -- "${candidates[@]}"
+    else
+        _files
+    fi
+}
+if [ "$funcstack[1]" = "_dk" ]; then
+    _dk "$@"
+else
+    compdef _dk dk
+fi
+"#;
+
+const FISH_SCRIPT: &str = r#"# dk completion for fish : dk completions fish | source
+function __dk_complete
+    set -l words (commandline -opc)
+    set -e words[1]
+    set -l candidates (dk __complete -- $words (commandline -ct) 2>/dev/null)
+    if test (count $candidates) -gt 0
+        printf '%s\n' $candidates
+    else
+        __fish_complete_path (commandline -ct)
+    end
+end
+complete -c dk -f -a '(__dk_complete)'
+"#;
+
+pub fn usage() {
+    cli::print_usage_section("SHELL");
+}
+
+/// Completion script of a shell, calling back `dk __complete`
+pub fn script(shell: CompletionShell) -> &'static str {
+    match shell {
+        CompletionShell::Bash => BASH_SCRIPT,
+        CompletionShell::Zsh => ZSH_SCRIPT,
+        CompletionShell::Fish => FISH_SCRIPT,
+    }
+}
+
+/// Complete a partial command line.
+///
+/// `words` are the words typed after `dk`, the last one being the word under
+/// completion (possibly empty). The subcommands and options come from the clap
+/// definition, the containers, images, volumes and OTS names are read live,
+/// only when the argument under completion expects them.
+pub fn complete(executor: &dyn CommandExecutor, config: &DkConfig, words: &[String]) -> Vec<String> {
+    let (current, typed) = match words.split_last() {
+        Some((current, typed)) => (current.as_str(), typed),
+        None => ("", words),
+    };
+    let mut root = Cli::command();
+    root.build();
+
+    // parcourir les mots déjà saisis pour trouver la commande et l'argument en cours
+    let mut command = &root;
+    let mut positional = 0;
+    let mut pending: Option<&Arg> = None;
+    let mut options_done = false;
+    for word in typed {
+        if pending.take().is_some() {
+            continue;
+        }
+        if word == "--" {
+            options_done = true;
+        } else if !options_done && word.starts_with('-') {
+            if !word.contains('=') {
+                pending = find_option(command, word).filter(|a| takes_value(a));
+            }
+        } else if let Some(sub) = command.find_subcommand(word).filter(|_| positional == 0) {
+            command = sub;
+        } else {
+            positional += 1;
+        }
+    }
+
+    let mut candidates = Vec::new();
+    let mut prefix = "";
+    if let Some(arg) = pending {
+        candidates = values(executor, config, arg);
+    } else if !options_done && current.starts_with("--") && current.contains('=') {
+        let (flag, _) = current.split_once('=').unwrap_or_default();
+        if let Some(arg) = find_option(command, flag).filter(|a| takes_value(a)) {
+            prefix = &current[..=flag.len()];
+            candidates = values(executor, config, arg);
+        }
+    } else if !options_done && current.starts_with('-') {
+        candidates = command
+            .get_arguments()
+            .filter(|a| !a.is_positional() && !a.is_hide_set())
+            .flat_map(|a| {
+                let long = a.get_long().map(|l| format!("--{}", l));
+                let short = a.get_short().map(|s| format!("-{}", s));
+                long.into_iter().chain(short)
+            })
+            .collect();
+    } else {
+        if positional == 0 {
+            candidates.extend(
+                command
+                    .get_subcommands()
+                    .filter(|c| !c.is_hide_set())
+                    .map(|c| c.get_name().to_string()),
+            );
+            if std::ptr::eq(command, &root) {
+                let commands = cli::command_names();
+                candidates.extend(config.aliases.keys().filter(|a| !commands.contains(a)).cloned());
+            }
+        }
+        let positionals: Vec<&Arg> = command.get_positionals().collect();
+        let arg = positionals
+            .get(positional)
+            .or_else(|| positionals.last().filter(|a| matches!(a.get_action(), ArgAction::Append)));
+        if let Some(arg) = arg {
+            candidates.extend(
+                values(executor, config, arg)
+                    .into_iter()
+                    .filter(|v| !typed.contains(v)),
+            );
+        }
+    }
+
+    let mut completions: Vec<String> = Vec::new();
+    for candidate in candidates {
+        let completion = format!("{}{}", prefix, candidate);
+        if completion.starts_with(current) && !completions.contains(&completion) {
+            completions.push(completion);
+        }
+    }
+    completions
+}
+
+fn find_option<'a>(command: &'a Command, word: &str) -> Option<&'a Arg> {
+    command.get_arguments().find(|a| match word.strip_prefix("--") {
+        Some(long) => a.get_long() == Some(long),
+        None => word.len() == 2 && a.get_short().is_some_and(|s| word.ends_with(s)),
+    })
+}
+
+fn takes_value(arg: &Arg) -> bool {
+    !matches!(
+        arg.get_action(),
+        ArgAction::SetTrue | ArgAction::SetFalse | ArgAction::Count | ArgAction::Help | ArgAction::Version
+    )
+}
+
+/// Values of an argument: the live docker objects named by its value name,
+/// or the possible values of the clap definition
+fn values(executor: &dyn CommandExecutor, config: &DkConfig, arg: &Arg) -> Vec<String> {
+    let value_name = arg
+        .get_value_names()
+        .and_then(|v| v.first())
+        .map(|v| v.as_str())
+        .unwrap_or_default();
+    match value_name {
+        "container" => container_helper::get_containers(executor)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|c| c.name)
+            .collect(),
+        "image" => image_helper::get_images(executor)
+            .unwrap_or_default()
+            .iter()
+            .filter(|i| i.repository != "<none>" && i.tag != "<none>")
+            .map(|i| i.reference())
+            .collect(),
+        "volume" => volume_helper::get_volumes(executor)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|v| v.name)
+            .collect(),
+        "ots" => config
+            .ots
+            .iter()
+            .map(|o| o.name.clone())
+            .chain(config.profiles.keys().map(|p| format!("@{}", p)))
+            .collect(),
+        _ => arg
+            .get_possible_values()
+            .iter()
+            .filter(|v| !v.is_hide_set())
+            .map(|v| v.get_name().to_string())
+            .collect(),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn complete_line(executor: &dyn CommandExecutor, line: &str) -> Vec<String> {
+        let mut words: Vec<String> = line.split(' ').map(String::from).collect();
+        words.remove(0);
+        complete(executor, &DkConfig::default_config(), &words)
+    }
+
+    #[test]
+    fn test_complete_commands_and_options() {
+        // aucune commande docker ne doit être lancée
+        let mock = MockCommandExecutor::new();
+        assert_eq!(complete_line(&mock, "dk o"), vec!["ots"]);
+        assert_eq!(complete_line(&mock, "dk im a"), vec!["archive"]);
+        assert_eq!(complete_line(&mock, "dk ps --"), vec!["--output", "--no-color", "--help"]);
+        assert_eq!(complete_line(&mock, "dk ps -o y"), vec!["yaml"]);
+        assert_eq!(complete_line(&mock, "dk vol --output=c"), vec!["--output=csv"]);
+        assert_eq!(complete_line(&mock, "dk completions "), vec!["bash", "zsh", "fish"]);
+        assert_eq!(complete_line(&mock, "dk ots up k"), vec!["kroki"]);
+        assert_eq!(complete_line(&mock, "dk ots down @"), vec!["@monitoring"]);
+        assert!(complete_line(&mock, "dk im load ").is_empty());
+        let mut config = DkConfig::default_config();
+        config.aliases.insert(String::from("clean"), String::from("sys prune && vol prune"));
+        config.aliases.insert(String::from("ps"), String::from("ps -o json"));
+        assert_eq!(complete(&mock, &config, &[String::from("c")]), vec!["config", "completions", "clean"]);
+        assert!(mock.calls().is_empty());
+    }
+
+    #[test]
+    fn test_complete_docker_objects() {
+        let mut mock = MockCommandExecutor::new();
+        mock.expect(&["docker", "ps", "..."], MockResponse::fixture("docker_ps.txt"))
+            .expect(&["docker", "images", "..."], MockResponse::fixture("docker_images.txt"))
+            .expect(&["docker", "volume", "ls", "..."], MockResponse::fixture("docker_volume_ls.txt"));
+
+        assert_eq!(complete_line(&mock, "dk rm ots_kroki ots_"), vec!["ots_portainer", "ots_sqlserver"]);
+        assert_eq!(complete_line(&mock, "dk im rm ng"), vec!["nginx:1.27"]);
+        assert_eq!(complete_line(&mock, "dk vol rm s"), vec!["sqlserver_data"]);
+    }
+}
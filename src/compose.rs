@@ -0,0 +1,387 @@
+use serde_yaml::{Mapping, Value};
+use std::path::{Component, Path, PathBuf};
+use crate::config::{DkConfig, Ots};
+use crate::dkutil::split_shell_words;
+
+/// Keys of a compose service converted into an `Ots` entry
+const SUPPORTED_KEYS: &[&str] = &[
+    "image", "container_name", "ports", "environment", "volumes", "restart", "command", "depends_on",
+];
+
+/// Convert the services of a compose file into OTS entries, the relative bind
+/// sources resolved from `dir` (the directory of the compose file).
+///
+/// Returns the entries and a warning for each key that is not converted.
+pub fn import(yaml: &str, dir: &Path) -> Result<(Vec<Ots>, Vec<String>), String> {
+    let root: Value = serde_yaml::from_str(yaml).map_err(|e| format!("Invalid compose file: {}", e))?;
+    let root = root.as_mapping().ok_or("Invalid compose file: expected a mapping")?;
+    let mut warnings = Vec::new();
+    for key in root.keys().filter_map(|k| k.as_str()) {
+        if !["services", "version", "name", "volumes"].contains(&key) {
+            warnings.push(format!("{}: unsupported, ignored", key));
+        }
+    }
+    // les volumes nommés sont créés par docker, leurs options (driver, external...) sont perdues
+    if let Some(volumes) = root.get("volumes").and_then(|v| v.as_mapping()) {
+        for (name, volume) in volumes {
+            let options: Vec<&str> = volume.as_mapping().into_iter().flat_map(|o| o.keys()).filter_map(|k| k.as_str()).collect();
+            if !options.is_empty() {
+                warnings.push(format!("volumes.{}: unsupported {}, ignored", name.as_str().unwrap_or_default(), options.join(", ")));
+            }
+        }
+    }
+    let services = root
+        .get("services")
+        .and_then(|s| s.as_mapping())
+        .ok_or("Invalid compose file: no services")?;
+
+    let mut entries = Vec::new();
+    for (name, service) in services {
+        let name = name.as_str().ok_or("Invalid compose file: service name")?;
+        let service = service
+            .as_mapping()
+            .ok_or_else(|| format!("services.{}: expected a mapping", name))?;
+        let unsupported: Vec<&str> = service
+            .keys()
+            .filter_map(|k| k.as_str())
+            .filter(|k| !SUPPORTED_KEYS.contains(k))
+            .collect();
+        if !unsupported.is_empty() {
+            warnings.push(format!("services.{}: unsupported {}, ignored", name, unsupported.join(", ")));
+        }
+        entries.push(import_service(name, service, dir)?);
+    }
+    Ok((entries, warnings))
+}
+
+fn import_service(name: &str, service: &Mapping, dir: &Path) -> Result<Ots, String> {
+    let at = |key: &str| format!("services.{}.{}", name, key);
+    let mut ots = Ots { name: name.to_string(), ..Default::default() };
+    ots.image = scalar(service.get("image"))
+        .ok_or_else(|| format!("services.{}: no image (build is not supported)", name))?;
+    ots.container_name = scalar(service.get("container_name"));
+    ots.restart = scalar(service.get("restart"));
+
+    for port in sequence(service.get("ports")) {
+        match port {
+            Value::Mapping(long) => {
+                let target = scalar(long.get("target")).ok_or_else(|| at("ports"))?;
+                let mut port = match scalar(long.get("published")) {
+                    Some(published) => format!("{}:{}", published, target),
+                    None => target,
+                };
+                if let Some(ip) = scalar(long.get("host_ip")) {
+                    port = format!("{}:{}", ip, port);
+                }
+                if let Some(protocol) = scalar(long.get("protocol")) {
+                    port = format!("{}/{}", port, protocol);
+                }
+                ots.ports.push(port);
+            }
+            other => ots.ports.push(scalar(Some(&other)).ok_or_else(|| at("ports"))?),
+        }
+    }
+
+    match service.get("environment") {
+        Some(Value::Mapping(environment)) => {
+            for (key, value) in environment {
+                let key = scalar(Some(key)).ok_or_else(|| at("environment"))?;
+                // sans valeur, compose reprend la variable de l'environnement
+                let value = match value {
+                    Value::Null => format!("${{{}}}", key),
+                    value => scalar(Some(value)).unwrap_or_default(),
+                };
+                ots.env.insert(key, value);
+            }
+        }
+        Some(Value::Sequence(environment)) => {
+            for variable in environment {
+                let variable = scalar(Some(variable)).ok_or_else(|| at("environment"))?;
+                let (key, value) = match variable.split_once('=') {
+                    Some((key, value)) => (key.to_string(), value.to_string()),
+                    None => (variable.clone(), format!("${{{}}}", variable)),
+                };
+                ots.env.insert(key, value);
+            }
+        }
+        _ => {}
+    }
+
+    for volume in sequence(service.get("volumes")) {
+        match volume {
+            Value::Mapping(long) => {
+                let mut fields = Vec::new();
+                let bind = scalar(long.get("type")).as_deref() == Some("bind");
+                for (key, field) in [("type", "type"), ("source", "src"), ("target", "dst")] {
+                    if let Some(mut value) = scalar(long.get(key)) {
+                        if key == "source" && bind {
+                            value = resolve(dir, &value);
+                        }
+                        fields.push(format!("{}={}", field, value));
+                    }
+                }
+                if long.get("read_only").and_then(|r| r.as_bool()) == Some(true) {
+                    fields.push(String::from("readonly"));
+                }
+                ots.mounts.push(fields.join(","));
+            }
+            other => {
+                let volume = scalar(Some(&other)).ok_or_else(|| at("volumes"))?;
+                let volume = match volume.split_once(':') {
+                    Some((source, rest)) if source.starts_with('.') => format!("{}:{}", resolve(dir, source), rest),
+                    _ => volume,
+                };
+                ots.volumes.push(volume);
+            }
+        }
+    }
+
+    ots.command = match service.get("command") {
+        Some(Value::String(command)) => split_shell_words(command).map_err(|e| format!("{}: {}", at("command"), e))?,
+        Some(Value::Sequence(_)) => sequence(service.get("command")).iter().filter_map(|a| scalar(Some(a))).collect(),
+        _ => Vec::new(),
+    };
+    ots.depends_on = match service.get("depends_on") {
+        Some(Value::Mapping(dependencies)) => dependencies.keys().filter_map(|d| scalar(Some(d))).collect(),
+        _ => sequence(service.get("depends_on")).iter().filter_map(|d| scalar(Some(d))).collect(),
+    };
+    Ok(ots)
+}
+
+/// A bind source relative to `dir` (`./data`, `../shared`) made absolute, other sources as-is
+fn resolve(dir: &Path, source: &str) -> String {
+    if !source.starts_with('.') {
+        return source.to_string();
+    }
+    let mut path = PathBuf::from(dir);
+    for component in Path::new(source).components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                path.pop();
+            }
+            other => path.push(other),
+        }
+    }
+    path.display().to_string()
+}
+
+/// Build a compose file from the OTS entries.
+///
+/// The `${VAR}` are kept as-is, compose replaces them the same way.
+/// Returns the YAML and a warning for each option that cannot be exported.
+pub fn export(config: &DkConfig, names: &[String]) -> Result<(String, Vec<String>), String> {
+    let names = if names.is_empty() {
+        config.ots.iter().map(|o| o.name.clone()).collect()
+    } else {
+        config.expand(names)?
+    };
+
+    let mut warnings = Vec::new();
+    let mut services = Mapping::new();
+    let mut volumes = Mapping::new();
+    for name in &names {
+        let ots = config
+            .ots
+            .iter()
+            .find(|o| o.name == *name)
+            .ok_or_else(|| format!("OTS {} not found", name))?
+            .resolved()?;
+        let mut service = Mapping::new();
+        service.insert(key("image"), Value::from(ots.image.clone()));
+        service.insert(key("container_name"), Value::from(ots.container_name()));
+        if !ots.ports.is_empty() {
+            service.insert(key("ports"), strings(&ots.ports));
+        }
+        if !ots.env.is_empty() {
+            let environment: Mapping = ots
+                .env
+                .iter()
+                .map(|(k, v)| (Value::from(k.clone()), Value::from(v.clone())))
+                .collect();
+            service.insert(key("environment"), Value::Mapping(environment));
+        }
+        let mut service_volumes: Vec<Value> = ots.volumes.iter().map(|v| Value::from(v.clone())).collect();
+        for mount in &ots.mounts {
+            service_volumes.push(Value::Mapping(export_mount(mount)));
+        }
+        if !service_volumes.is_empty() {
+            service.insert(key("volumes"), Value::Sequence(service_volumes));
+        }
+        if let Some(restart) = &ots.restart {
+            service.insert(key("restart"), Value::from(restart.clone()));
+        }
+        if !ots.command.is_empty() {
+            service.insert(key("command"), strings(&ots.command));
+        }
+        if !ots.depends_on.is_empty() {
+            service.insert(key("depends_on"), strings(&ots.depends_on));
+        }
+        if !ots.extra_args.is_empty() {
+            warnings.push(format!("{}: extra_args not exported ({})", name, ots.extra_args.join(" ")));
+        }
+        if ots.ready.is_some() {
+            warnings.push(format!("{}: ready probe not exported", name));
+        }
+        if ots.url.is_some() {
+            warnings.push(format!("{}: url not exported", name));
+        }
+        for volume in ots.named_volumes() {
+            volumes.insert(Value::from(volume), Value::Mapping(Mapping::new()));
+        }
+        services.insert(Value::from(name.clone()), Value::Mapping(service));
+    }
+
+    let mut root = Mapping::new();
+    root.insert(key("services"), Value::Mapping(services));
+    if !volumes.is_empty() {
+        root.insert(key("volumes"), Value::Mapping(volumes));
+    }
+    let yaml = serde_yaml::to_string(&root).map_err(|e| e.to_string())?;
+    Ok((yaml, warnings))
+}
+
+/// Long syntax of a `--mount` (`type=volume,src=data,dst=/data`)
+fn export_mount(mount: &str) -> Mapping {
+    let mut long = Mapping::new();
+    for field in mount.split(',') {
+        match field.split_once('=').unwrap_or((field, "")) {
+            ("type", value) => long.insert(key("type"), Value::from(value)),
+            ("src" | "source", value) => long.insert(key("source"), Value::from(value)),
+            ("dst" | "destination" | "target", value) => long.insert(key("target"), Value::from(value)),
+            ("readonly" | "ro", "" | "true" | "1") => long.insert(key("read_only"), Value::from(true)),
+            _ => None,
+        };
+    }
+    long
+}
+
+fn key(name: &str) -> Value {
+    Value::from(name)
+}
+
+fn strings(values: &[String]) -> Value {
+    Value::Sequence(values.iter().map(|v| Value::from(v.clone())).collect())
+}
+
+fn scalar(value: Option<&Value>) -> Option<String> {
+    match value? {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+fn sequence(value: Option<&Value>) -> Vec<Value> {
+    match value {
+        Some(Value::Sequence(values)) => values.clone(),
+        _ => Vec::new(),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    fn env(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    const COMPOSE: &str = r#"
+version: "3.8"
+services:
+  grafana:
+    image: grafana/grafana:11.0.0
+    ports:
+      - "3000:3000"
+      - target: 9090
+        published: 19090
+        protocol: udp
+    environment:
+      GF_SECURITY_ADMIN_PASSWORD: ${GRAFANA_PASSWORD}
+      GF_LOG_LEVEL: debug
+    volumes:
+      - grafana_data:/var/lib/grafana
+      - type: bind
+        source: ./provisioning
+        target: /etc/grafana/provisioning
+        read_only: true
+    restart: unless-stopped
+    depends_on:
+      prometheus:
+        condition: service_started
+    healthcheck:
+      test: ["CMD", "curl", "-f", "http://localhost:3000"]
+    build: .
+  prometheus:
+    image: prom/prometheus
+    container_name: prometheus
+    environment:
+      - TZ=Europe/Paris
+      - PROMETHEUS_TOKEN
+    volumes:
+      - ./prometheus.yml:/etc/prometheus/prometheus.yml:ro
+      - ../shared:/shared
+    command: --config.file=/etc/prometheus/prometheus.yml --web.enable-lifecycle
+networks:
+  default: {}
+volumes:
+  grafana_data:
+    driver: local
+    external: true
+"#;
+
+    #[test]
+    fn test_import() {
+        let (entries, warnings) = import(COMPOSE, Path::new("/srv/monitoring")).unwrap();
+        assert_eq!(
+            warnings,
+            vec![
+                "networks: unsupported, ignored",
+                "volumes.grafana_data: unsupported driver, external, ignored",
+                "services.grafana: unsupported healthcheck, build, ignored",
+            ]
+        );
+        let grafana = &entries[0];
+        assert_eq!(grafana.name, "grafana");
+        assert_eq!(grafana.ports, vec!["3000:3000", "19090:9090/udp"]);
+        assert_eq!(grafana.env, env(&[("GF_LOG_LEVEL", "debug"), ("GF_SECURITY_ADMIN_PASSWORD", "${GRAFANA_PASSWORD}")]));
+        assert_eq!(grafana.volumes, vec!["grafana_data:/var/lib/grafana"]);
+        assert_eq!(grafana.mounts, vec!["type=bind,src=/srv/monitoring/provisioning,dst=/etc/grafana/provisioning,readonly"]);
+        assert_eq!(grafana.depends_on, vec!["prometheus"]);
+        let prometheus = &entries[1];
+        assert_eq!(prometheus.container_name.as_deref(), Some("prometheus"));
+        assert_eq!(prometheus.env, env(&[("PROMETHEUS_TOKEN", "${PROMETHEUS_TOKEN}"), ("TZ", "Europe/Paris")]));
+        assert_eq!(prometheus.volumes, vec!["/srv/monitoring/prometheus.yml:/etc/prometheus/prometheus.yml:ro", "/srv/shared:/shared"]);
+        assert_eq!(prometheus.command, vec!["--config.file=/etc/prometheus/prometheus.yml", "--web.enable-lifecycle"]);
+
+        let (entries, _) = import("services:\n  app:\n    image: app\n    environment:\n      TOKEN:\n", Path::new("/")).unwrap();
+        assert_eq!(entries[0].env, env(&[("TOKEN", "${TOKEN}")]));
+        assert!(import("services:\n  app:\n    build: .\n", Path::new("/")).is_err());
+        assert!(import("- not a compose file", Path::new("/")).is_err());
+    }
+
+    #[test]
+    fn test_export() {
+        let config = DkConfig::default_config();
+        let (yaml, warnings) = export(&config, &[String::from("rabbitmq"), String::from("sqlserver")]).unwrap();
+        assert_eq!(warnings, vec!["rabbitmq: ready probe not exported", "rabbitmq: url not exported", "sqlserver: ready probe not exported"]);
+        assert!(yaml.starts_with("services:\n  rabbitmq:\n    image: rabbitmq:4.1.0-management\n    container_name: ots_rabbitmq\n"));
+        assert!(yaml.contains("      SA_PASSWORD: ${SA_PASSWORD}\n"));
+        assert!(yaml.ends_with("volumes:\n  ots_rabbitmq: {}\n  sqlserver_data: {}\n"));
+
+        // l'export se relit à l'identique
+        let (entries, warnings) = import(&yaml, Path::new("/")).unwrap();
+        assert!(warnings.is_empty());
+        let rabbitmq = config.ots[4].resolved().unwrap();
+        assert_eq!(entries[0], Ots { container_name: Some(String::from("ots_rabbitmq")), ready: None, url: None, ..rabbitmq });
+        assert_eq!(entries[0].mounts, vec!["type=volume,src=ots_rabbitmq,dst=/var/lib/rabbitmq"]);
+
+        let (_, warnings) = export(&config, &[String::from("@monitoring")]).unwrap();
+        assert_eq!(warnings, vec!["ctop: extra_args not exported (-t)"]);
+        assert!(export(&config, &[String::from("unknown")]).is_err());
+    }
+}
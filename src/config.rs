@@ -1,23 +1,506 @@
 use serde::{Deserialize, Serialize};
-use std::{fs};
-use std::path::{PathBuf};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::{env, fs};
+use std::path::{Path, PathBuf};
 use std::io::{self, Write};
+use crate::alias_helper;
+use crate::config_format::{ConfigFormat, FormatError};
+use crate::dkutil::{print_warning, split_shell_words};
+use crate::image_archive::ImageRef;
+use crate::variables::{self, Variables};
 
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
 pub struct DkConfig {
+    /// JSON Schema used by the editors (`dk config schema`)
+    #[serde(rename = "$schema", default, skip_serializing_if = "Option::is_none")]
+    pub schema: Option<String>,
+    /// Version of the document, see `migrate`
+    #[serde(default)]
+    pub version: u32,
     pub ots: Vec<Ots>,
+    /// Named sets of services (`dk ots up @monitoring`)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub profiles: BTreeMap<String, Vec<String>>,
+    /// Commands expanded before dispatch (`"clean": "sys prune && vol prune"`)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub aliases: BTreeMap<String, String>,
+    /// Fields dk does not know, kept to be reported by the check
+    #[serde(flatten)]
+    pub unknown: BTreeMap<String, Value>,
+    /// Values of the `${VAR}` of the services (environment, `.env`, secrets)
+    #[serde(skip)]
+    pub variables: Variables,
 }
 
-#[derive(Serialize, Deserialize)]
+/// A problem found in the configuration, located by the JSON path of the entry
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigProblem {
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for ConfigProblem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.message)
+    }
+}
+
+/// Why the configuration file could not be loaded
+#[derive(Debug)]
+pub enum ConfigError {
+    /// No file yet, the only case where the defaults may be written
+    Missing(PathBuf),
+    /// No home directory and no `DK_CONFIG`
+    NoHome,
+    Permission(PathBuf),
+    Parse { path: PathBuf, line: usize, column: usize, message: String },
+    /// Valid JSON that dk cannot use (e.g. a version from a newer dk)
+    Invalid { path: PathBuf, message: String },
+    Io(PathBuf, io::Error),
+}
+
+impl ConfigError {
+    fn from_io(path: &Path, e: io::Error) -> ConfigError {
+        match e.kind() {
+            io::ErrorKind::NotFound => ConfigError::Missing(path.to_path_buf()),
+            io::ErrorKind::PermissionDenied => ConfigError::Permission(path.to_path_buf()),
+            _ => ConfigError::Io(path.to_path_buf(), e),
+        }
+    }
+
+    fn from_format(path: &Path, e: FormatError) -> ConfigError {
+        ConfigError::Parse { path: path.to_path_buf(), line: e.line, column: e.column, message: e.message }
+    }
+
+    /// No configuration file: the defaults can be used, and written
+    pub fn is_missing(&self) -> bool {
+        matches!(self, ConfigError::Missing(_) | ConfigError::NoHome)
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Missing(path) => write!(f, "{} not found (dk config init creates it)", path.display()),
+            ConfigError::NoHome => write!(f, "Unable to retrieve home directory, set DK_CONFIG"),
+            ConfigError::Permission(path) => write!(f, "{}: permission denied", path.display()),
+            ConfigError::Parse { path, line, column, message } => {
+                write!(f, "{}:{}:{}: {}", path.display(), line, column, message)
+            }
+            ConfigError::Invalid { path, message } => write!(f, "{}: {}", path.display(), message),
+            ConfigError::Io(path, e) => write!(f, "{}: {}", path.display(), e),
+        }
+    }
+}
+
+/// An OTS service: the container dk creates with `docker run -d`
+///
+/// Legacy entries only hold a `command_line` (a whole `docker run` line), it is
+/// split like a shell does and converted into the typed fields when used.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
 pub struct Ots {
     pub name: String,
-    pub port: u32,
-    pub command_line: String
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub image: String,
+    /// Name of the container, `ots_<name>` by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub container_name: Option<String>,
+    /// Published ports (`host:container`, as `-p`)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ports: Vec<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+    /// Volumes and bind mounts (as `-v`)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub volumes: Vec<String>,
+    /// Mounts (as `--mount`)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mounts: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub restart: Option<String>,
+    /// Other `docker run` options, passed as-is
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub extra_args: Vec<String>,
+    /// Command and arguments given to the image
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub command: Vec<String>,
+    /// Services started before this one (and stopped after it)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub depends_on: Vec<String>,
+    /// Readiness probe used by `dk ots up --wait`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ready: Option<Probe>,
+    /// URL shown by `dk ots ls`, by default the first port published to a web port
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// Legacy definition: a whole `docker run` command line
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command_line: Option<String>,
+    #[serde(flatten)]
+    pub unknown: BTreeMap<String, Value>,
+}
+
+/// How to know that a service is ready, once its container is running
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Probe {
+    /// The port accepts TCP connections on localhost
+    Tcp { port: u16 },
+    /// A GET on the URL answers the status code
+    Http {
+        url: String,
+        #[serde(default = "default_http_status")]
+        status: u16,
+    },
+    /// The HEALTHCHECK of the image reports the container as healthy
+    Docker,
+}
+
+fn default_http_status() -> u16 {
+    200
 }
 
-const CONFIG_DIRECTORY: &str = ".dk";
-const CONFIG_FILE_NAME: &str = "dk_config.json";
+/// `docker run` options without value, the other options take one
+const FLAG_OPTIONS: &[&str] = &[
+    "--detach", "--interactive", "--tty", "--rm", "--init", "--privileged",
+    "--read-only", "--publish-all", "--no-healthcheck", "--oom-kill-disable",
+];
+const FLAG_SHORT_OPTIONS: &str = "dtiP";
+
+/// Container ports known to serve HTTP or HTTPS, to guess the URL of a service
+const HTTP_PORTS: &[u16] = &[80, 3000, 5000, 8000, 8080, 8081, 8888, 9000, 9090];
+const HTTPS_PORTS: &[u16] = &[443, 8443, 9443];
+/// Container ports of databases and brokers, which never get a URL
+const NON_HTTP_PORTS: &[u16] = &[1433, 1521, 2181, 3306, 5432, 5672, 6379, 9092, 11211, 27017];
+
+impl Ots {
+    /// Name of the container of the service
+    pub fn container_name(&self) -> String {
+        self.container_name
+            .clone()
+            .unwrap_or_else(|| format!("ots_{}", self.name))
+    }
+
+    /// Host port of the first published port
+    pub fn host_port(&self) -> Option<u16> {
+        self.ports.iter().find_map(|p| parse_port(p).ok().flatten().map(|(port, _)| port))
+    }
+
+    /// URL of the service: `url`, or the first TCP port published to a known web port
+    pub fn status_url(&self) -> Option<String> {
+        if self.url.is_some() {
+            return self.url.clone();
+        }
+        self.ports.iter().find_map(|p| {
+            let (host, container) = published_port(p)?;
+            let scheme = if HTTP_PORTS.contains(&container) {
+                "http"
+            } else if HTTPS_PORTS.contains(&container) {
+                "https"
+            } else {
+                return None;
+            };
+            Some(format!("{}://localhost:{}", scheme, host))
+        })
+    }
+
+    /// Named volumes of the service (from `volumes` and `mounts`), kept when the container is recreated
+    pub fn named_volumes(&self) -> Vec<String> {
+        let from_volumes = self.volumes.iter().filter_map(|v| v.split(':').next());
+        let from_mounts = self.mounts.iter().filter_map(|m| {
+            let mut fields = m.split(',').filter_map(|f| f.split_once('='));
+            let mut source = None;
+            let mut is_volume = true;
+            for (key, value) in fields.by_ref() {
+                match key {
+                    "type" => is_volume = value == "volume",
+                    "src" | "source" => source = Some(value),
+                    _ => {}
+                }
+            }
+            source.filter(|_| is_volume)
+        });
+        from_volumes
+            .chain(from_mounts)
+            .filter(|v| !v.is_empty() && !v.starts_with('/') && !v.starts_with('.'))
+            .map(String::from)
+            .collect()
+    }
+
+    /// True when the definition comes from a legacy `command_line`
+    pub fn is_legacy(&self) -> bool {
+        self.command_line.is_some() && self.image.is_empty()
+    }
+
+    /// Replace the `${VAR}` of every field, except the name and the dependencies;
+    /// a variable not set is an error, unless `unset` gives its replacement
+    pub fn interpolated(&self, lookup: &dyn Fn(&str) -> Option<String>, unset: Option<&str>) -> Result<Ots, String> {
+        let mut value = serde_json::to_value(self).map_err(|e| e.to_string())?;
+        if let Value::Object(fields) = &mut value {
+            for (key, field) in fields.iter_mut() {
+                if key != "name" && key != "depends_on" {
+                    interpolate_value(field, lookup, unset).map_err(|e| format!("OTS {}: {}", self.name, e))?;
+                }
+            }
+        }
+        serde_json::from_value(value).map_err(|e| format!("OTS {}: {}", self.name, e))
+    }
+
+    /// The typed definition of the service, converted from `command_line` for a legacy entry
+    pub fn resolved(&self) -> Result<Ots, String> {
+        match &self.command_line {
+            Some(line) if self.is_legacy() => {
+                let ots = Self::from_command_line(&self.name, line)?;
+                Ok(Ots { depends_on: self.depends_on.clone(), ready: self.ready.clone(), ..ots })
+            }
+            _ => Ok(self.clone()),
+        }
+    }
+
+    /// Convert a `docker run` command line into a typed definition
+    pub fn from_command_line(name: &str, line: &str) -> Result<Ots, String> {
+        let words = split_shell_words(line)?;
+        let mut words = words.into_iter();
+        if words.next().as_deref() != Some("docker") || words.next().as_deref() != Some("run") {
+            return Err(format!("OTS {}: the command line must start with 'docker run'", name));
+        }
+
+        let mut ots = Ots { name: name.to_string(), ..Default::default() };
+        while let Some(word) = words.next() {
+            if !word.starts_with('-') || word == "-" {
+                ots.image = word;
+                ots.command = words.collect();
+                return Ok(ots);
+            }
+            if word == "-d" || word == "--detach" {
+                // dk lance toujours les services en arrière-plan
+                continue;
+            }
+            let is_flag = FLAG_OPTIONS.contains(&word.as_str())
+                || (!word.starts_with("--") && word[1..].chars().all(|c| FLAG_SHORT_OPTIONS.contains(c)));
+            if is_flag {
+                ots.extra_args.push(word);
+                continue;
+            }
+
+            let (option, value) = match word.split_once('=').filter(|_| word.starts_with("--")) {
+                Some((option, value)) => (option.to_string(), value.to_string()),
+                None => {
+                    let value = words
+                        .next()
+                        .ok_or_else(|| format!("OTS {}: missing value for {}", name, word))?;
+                    (word, value)
+                }
+            };
+            match option.as_str() {
+                "--name" => ots.container_name = Some(value),
+                "-p" | "--publish" => ots.ports.push(value),
+                "-v" | "--volume" => ots.volumes.push(value),
+                "--mount" => ots.mounts.push(value),
+                "--restart" => ots.restart = Some(value),
+                "-e" | "--env" if value.contains('=') => {
+                    let (key, value) = value.split_once('=').unwrap_or_default();
+                    ots.env.insert(key.to_string(), value.to_string());
+                }
+                _ => {
+                    ots.extra_args.push(option);
+                    ots.extra_args.push(value);
+                }
+            }
+        }
+        Err(format!("OTS {}: no image in the command line", name))
+    }
+
+    /// The `docker run` command creating the container of the service
+    pub fn docker_args(&self) -> Result<Vec<String>, String> {
+        let ots = self.resolved()?;
+        if ots.image.is_empty() {
+            return Err(format!("OTS {}: no image", ots.name));
+        }
+        let mut args: Vec<String> = ["docker", "run", "-d", "--name"].map(String::from).into();
+        args.push(ots.container_name());
+        for port in &ots.ports {
+            args.extend([String::from("-p"), port.clone()]);
+        }
+        for (key, value) in &ots.env {
+            args.extend([String::from("-e"), format!("{}={}", key, value)]);
+        }
+        for volume in &ots.volumes {
+            args.extend([String::from("-v"), volume.clone()]);
+        }
+        for mount in &ots.mounts {
+            args.extend([String::from("--mount"), mount.clone()]);
+        }
+        if let Some(restart) = &ots.restart {
+            args.extend([String::from("--restart"), restart.clone()]);
+        }
+        args.extend(ots.extra_args);
+        args.push(ots.image);
+        args.extend(ots.command);
+        Ok(args)
+    }
+}
+
+fn interpolate_value(value: &mut Value, lookup: &dyn Fn(&str) -> Option<String>, unset: Option<&str>) -> Result<(), String> {
+    match value {
+        Value::String(text) => *text = variables::interpolate_or(text, lookup, unset)?,
+        Value::Array(values) => {
+            for value in values {
+                interpolate_value(value, lookup, unset)?;
+            }
+        }
+        Value::Object(fields) => {
+            for value in fields.values_mut() {
+                interpolate_value(value, lookup, unset)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Host port and protocol of a published port (`[ip:][host:]container[/proto]`),
+/// `None` when no host port is given
+pub fn parse_port(port: &str) -> Result<Option<(u16, String)>, String> {
+    let invalid = || format!("invalid published port '{}'", port);
+    let (port, protocol) = port.split_once('/').unwrap_or((port, "tcp"));
+    if !["tcp", "udp", "sctp"].contains(&protocol) {
+        return Err(invalid());
+    }
+    let parse = |range: &str| -> Result<u16, String> {
+        let first = range.split_once('-').map_or(range, |(first, last)| {
+            if last.parse::<u16>().is_err() { "" } else { first }
+        });
+        first.parse::<u16>().map_err(|_| invalid())
+    };
+    let mut parts = port.rsplitn(3, ':');
+    parse(parts.next().unwrap_or_default())?;
+    match parts.next() {
+        None | Some("") => Ok(None),
+        Some(host) => Ok(Some((parse(host)?, protocol.to_string()))),
+    }
+}
+
+/// Host and container ports of a published TCP port (`8080:80`, `127.0.0.1:8080:80/tcp`)
+fn published_port(port: &str) -> Option<(u16, u16)> {
+    let (port, protocol) = port.split_once('/').unwrap_or((port, "tcp"));
+    if protocol != "tcp" {
+        return None;
+    }
+    let first = |range: &str| range.split('-').next().and_then(|p| p.parse::<u16>().ok());
+    let mut parts = port.rsplitn(3, ':');
+    let container = first(parts.next()?)?;
+    let host = first(parts.next()?)?;
+    Some((host, container))
+}
+
+fn strings(values: &[&str]) -> Vec<String> {
+    values.iter().map(|v| v.to_string()).collect()
+}
+
+/// Version of the configuration written by this dk
+pub const CONFIG_VERSION: u32 = 2;
+
+/// Upgrades of the configuration, `MIGRATIONS[i]` goes from version `i + 1` to `i + 2`
+const MIGRATIONS: &[fn(&mut Value)] = &[migrate_v1_to_v2];
+
+/// Upgrade a configuration document to `CONFIG_VERSION` and return the version
+/// it had, a document without `version` being a version 1 document
+pub fn migrate(document: &mut Value) -> Result<u32, String> {
+    let version = match document.get("version") {
+        None => 1,
+        Some(v) => v
+            .as_u64()
+            .filter(|v| *v >= 1)
+            .ok_or_else(|| format!("invalid version {}", v))? as u32,
+    };
+    if version > CONFIG_VERSION {
+        return Err(format!(
+            "version {} comes from a newer dk (this one reads up to version {}), upgrade dk",
+            version, CONFIG_VERSION
+        ));
+    }
+    for step in &MIGRATIONS[version as usize - 1..] {
+        step(document);
+    }
+    if let Some(object) = document.as_object_mut() {
+        object.insert(String::from("version"), Value::from(CONFIG_VERSION));
+    }
+    Ok(version)
+}
+
+/// Version 1 entries have a `port` and a whole `command_line`, version 2
+/// entries have typed fields (the port comes from `ports`).
+/// A command line that does not parse is kept, the check reports it.
+fn migrate_v1_to_v2(document: &mut Value) {
+    for entry in document["ots"].as_array_mut().into_iter().flatten() {
+        let Some(object) = entry.as_object_mut() else {
+            continue;
+        };
+        // le port de la v1 était celui de l'URL affichée (0 : aucune)
+        let port = object.remove("port").and_then(|p| p.as_u64()).filter(|p| *p != 0);
+        let name = object.get("name").and_then(|n| n.as_str()).unwrap_or_default().to_string();
+        let line = object.get("command_line").and_then(|l| l.as_str()).unwrap_or_default();
+        let typed = Ots::from_command_line(&name, line).ok();
+        let published: Vec<(u16, u16)> = typed
+            .iter()
+            .flat_map(|o| o.ports.iter().filter_map(|p| published_port(p)))
+            .collect();
+        let target = |port: u64| published.iter().find(|(host, _)| u64::from(*host) == port).map(|(_, c)| *c);
+        let url = port
+            .filter(|p| target(*p).is_none_or(|c| !NON_HTTP_PORTS.contains(&c)))
+            .map(|port| {
+                let tls = target(port).is_some_and(|c| HTTPS_PORTS.contains(&c));
+                format!("{}://localhost:{}", if tls { "https" } else { "http" }, port)
+            });
+        // l'URL n'est écrite que si elle diffère de celle déduite des ports
+        if url.is_some() && url != typed.as_ref().and_then(|o| o.status_url()) {
+            object.entry("url").or_insert(Value::from(url));
+        }
+        let Some(mut ots) = typed else {
+            continue;
+        };
+        if ots.container_name.as_deref() == Some(&format!("ots_{}", name)) {
+            ots.container_name = None;
+        }
+        object.remove("command_line");
+        if let Value::Object(typed) = serde_json::to_value(&ots).unwrap_or_default() {
+            for (field, value) in typed {
+                object.entry(field).or_insert(value);
+            }
+        }
+    }
+}
+
+/// A service name is part of its container name (`ots_<name>`)
+fn check_name(name: &str) -> Result<(), String> {
+    let valid = name.starts_with(|c: char| c.is_ascii_alphanumeric())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || "_.-".contains(c));
+    if valid {
+        Ok(())
+    } else {
+        Err(format!("Invalid OTS name '{}' (letters, digits, '_', '.' and '-')", name))
+    }
+}
+
+pub const CONFIG_DIRECTORY: &str = ".dk";
+pub const CONFIG_FILE_NAME: &str = "dk_config.json";
+/// Names of the configuration file, by order of preference when several exist
+const CONFIG_FILE_NAMES: &[&str] = &[CONFIG_FILE_NAME, "dk_config.toml", "dk_config.yaml", "dk_config.yml"];
+
+/// The configuration file of a directory, whatever its format
+pub fn find_config_file(dir: &Path) -> Option<PathBuf> {
+    CONFIG_FILE_NAMES.iter().map(|name| dir.join(name)).find(|f| f.is_file())
+}
+
+/// A file next to `path`, named after it (`dk_config.toml.bak`)
+pub fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    path.with_file_name(format!("{}.{}", name, suffix))
+}
 
 
 impl DkConfig {
@@ -27,161 +510,771 @@ impl DkConfig {
     /// This method serializes the `DkConfig` instance into a JSON string
     /// and writes it to the configuration file located in the user's home directory.
     pub fn save_to_file(&self) -> io::Result<()> {
-        let config_path = Self::get_config_path();
-        let json = serde_json::to_string_pretty(self)?;
-        let mut file = fs::File::create(config_path)?;
-        file.write_all(json.as_bytes())?;
-        Ok(())
+        self.save_to(&Self::get_config_path()?)
+    }
+
+    /// Write the configuration to `path` atomically, in the format of its
+    /// extension: a temporary file renamed over the previous one, which is
+    /// kept as `<file>.bak`
+    pub fn save_to(&self, path: &Path) -> io::Result<()> {
+        let mut document = serde_json::to_value(self)?;
+        document["version"] = Value::from(CONFIG_VERSION);
+        let text = ConfigFormat::from_path(path).serialize(&document).map_err(io::Error::other)?;
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            Self::ensure_config_directory_exists(dir)?;
+        }
+        let temp = sibling(path, "tmp");
+        {
+            let mut file = fs::File::create(&temp)?;
+            file.write_all(text.as_bytes())?;
+            file.sync_all()?;
+        }
+        if path.exists() {
+            fs::set_permissions(&temp, fs::metadata(path)?.permissions())?;
+            fs::copy(path, sibling(path, "bak"))?;
+            // un fichier d'une version précédente est aussi gardé sous son numéro de version
+            if let Some(version) = Self::stored_version(path).filter(|v| *v < CONFIG_VERSION) {
+                let backup = sibling(path, &format!("v{}.bak", version));
+                if !backup.exists() {
+                    fs::copy(path, &backup)?;
+                    // sur la sortie d'erreur : la sortie standard peut être un résultat
+                    print_warning(&format!(
+                        "{} migrated from version {} to {} (original kept in {})",
+                        path.display(),
+                        version,
+                        CONFIG_VERSION,
+                        backup.display()
+                    ));
+                }
+            }
+        }
+        fs::rename(&temp, path)
+    }
+
+    /// Version of the configuration stored in a file (1 when it has none)
+    pub fn stored_version(path: &Path) -> Option<u32> {
+        let text = fs::read_to_string(path).ok()?;
+        let document: Value = ConfigFormat::from_path(path).parse(&text).ok()?;
+        match document.get("version") {
+            None => Some(1),
+            Some(version) => version.as_u64().and_then(|v| u32::try_from(v).ok()),
+        }
+    }
+
+    /// Load a DkConfig from the global configuration file
+    ///
+    /// This method reads the configuration file, deserializes its JSON, TOML
+    /// or YAML content, and returns a `DkConfig` instance.
+    pub fn load_from_file() -> Result<DkConfig, ConfigError> {
+        let config_path = Self::get_config_path().map_err(|_| ConfigError::NoHome)?;
+        Self::read_file(&config_path)
     }
 
-    /// Load a DkConfig from a JSON file
+    /// Read a configuration file, telling a missing file from an invalid one.
     ///
-    /// This method reads the configuration file, deserializes its JSON content,
-    /// and returns a `DkConfig` instance.
-    pub fn load_from_file() -> io::Result<DkConfig> {
-        let config_path = Self::get_config_path();
-        let json = fs::read_to_string(config_path)?;
-        let config: DkConfig = serde_json::from_str(&json)?;
+    /// An older document is migrated in memory only, `version` keeping the
+    /// version of the file: it is written by the next save (or `dk config migrate`).
+    pub fn read_file(path: &Path) -> Result<DkConfig, ConfigError> {
+        let text = fs::read_to_string(path).map_err(|e| ConfigError::from_io(path, e))?;
+        let format = ConfigFormat::from_path(path);
+        let mut document: Value = format.parse(&text).map_err(|e| ConfigError::from_format(path, e))?;
+        let invalid = |message: String| ConfigError::Invalid { path: path.to_path_buf(), message };
+        let version = migrate(&mut document).map_err(invalid)?;
+        if version == CONFIG_VERSION {
+            // relu depuis le texte pour garder la position des erreurs
+            return format.parse(&text).map_err(|e| ConfigError::from_format(path, e));
+        }
+
+        let mut config: DkConfig = serde_json::from_value(document).map_err(|e| invalid(e.to_string()))?;
+        config.version = version;
         Ok(config)
     }
 
     /// Create a default DkConfig instance
     ///
     /// This method creates a default configuration with predefined OTS services
-    /// and saves it to the configuration file. 
+    /// and saves it to the configuration file, only called when there is no file.
     /// It returns the default `DkConfig` instance.
     pub fn create_default() -> DkConfig {
-        let default_config = DkConfig {
+        let default_config = Self::default_config();
+        
+        // sans répertoire personnel ni DK_CONFIG, la configuration reste en mémoire
+        if Self::get_config_path().is_ok() {
+            match default_config.save_to_file() {
+                Ok(_) => {},
+                Err(e) => 
+                eprintln!("Error while saving the default configuration: {}", e),
+            }
+        }
+
+        default_config
+    }
+
+    /// Build the default configuration with the predefined OTS services
+    pub fn default_config() -> DkConfig {
+        DkConfig {
             ots: vec![
                 Ots {
                     name: String::from("portainer"),
-                    port: 25003,
-                    command_line: String::from(
-                    "docker run -d \
-                    --name ots_portainer \
-                    -p 9000:9000 \
-                    -p 25003:9443 \
-                    -v /var/run/docker.sock:/var/run/docker.sock \
-                    -v portainer_data:/data \
-                    --restart unless-stopped \
-                                        portainer/portainer-ce:latest")
+                    image: String::from("portainer/portainer-ce:latest"),
+                    ports: strings(&["9000:9000", "25003:9443"]),
+                    volumes: strings(&["/var/run/docker.sock:/var/run/docker.sock", "portainer_data:/data"]),
+                    restart: Some(String::from("unless-stopped")),
+                    ..Default::default()
                 },
-
                 Ots {
-                name:String::from("sqlserver"),
-                port:1433,
-                command_line: String::from(
-                "docker run -d \
-                --name ots_sqlserver \
-                -v sqlserver_data:/var/opt/mssql \
-                -p 1433:1433 \
-                -e ACCEPT_EULA=Y \
-                -e SA_PASSWORD=Sh@dokN0tD€ad! \
-                --restart unless-stopped \
-                mcr.microsoft.com/mssql/server:2022-latest")
+                    name: String::from("sqlserver"),
+                    image: String::from("mcr.microsoft.com/mssql/server:2022-latest"),
+                    ports: strings(&["1433:1433"]),
+                    env: BTreeMap::from([
+                        (String::from("ACCEPT_EULA"), String::from("Y")),
+                        (String::from("SA_PASSWORD"), String::from("${SA_PASSWORD}")),
+                    ]),
+                    volumes: strings(&["sqlserver_data:/var/opt/mssql"]),
+                    restart: Some(String::from("unless-stopped")),
+                    ready: Some(Probe::Tcp { port: 1433 }),
+                    ..Default::default()
                 },
-
                 Ots {
                     name: String::from("kroki"),
-                    port: 25100,
-                    command_line: String::from(
-                        "docker run -d \
-                        --name ots_kroki \
-                        -p 25100:8000 \
-                        --restart unless-stopped \
-                        yuzutech/kroki")
+                    image: String::from("yuzutech/kroki"),
+                    ports: strings(&["25100:8000"]),
+                    restart: Some(String::from("unless-stopped")),
+                    ready: Some(Probe::Http { url: String::from("http://localhost:25100/health"), status: 200 }),
+                    ..Default::default()
                 },
-
                 Ots {
                     name: String::from("excalidraw"),
-                    port: 25101,
-                    command_line: String::from(
-                        "docker run -d \
-                        --name ots_excalidraw \
-                        -p 25000:80 \
-                        --restart unless-stopped \
-                        excalidraw/excalidraw")
+                    image: String::from("excalidraw/excalidraw"),
+                    ports: strings(&["25000:80"]),
+                    restart: Some(String::from("unless-stopped")),
+                    ..Default::default()
                 },
                 Ots {
                     name: String::from("rabbitmq"),
-                    port: 15672,
-                    command_line: String::from(
-                        "docker run -d \
-                        --name ots_rabbitmq \
-                        -p 15672:15672 \
-                        -p 5672:5672 \
-                        --mount type=volume,src=ots_rabbitmq,dst=/var/lib/rabbitmq \
-                        --restart unless-stopped \
-                        rabbitmq:4.1.0-management")
+                    image: String::from("rabbitmq:4.1.0-management"),
+                    ports: strings(&["15672:15672", "5672:5672"]),
+                    mounts: strings(&["type=volume,src=ots_rabbitmq,dst=/var/lib/rabbitmq"]),
+                    restart: Some(String::from("unless-stopped")),
+                    ready: Some(Probe::Tcp { port: 5672 }),
+                    url: Some(String::from("http://localhost:15672")),
+                    ..Default::default()
                 },
                 Ots {
                     name: String::from("ctop"),
-                    port: 0,
-                    command_line: String::from(
-                        "docker run -d \
-                        --name ots_ctop \
-                        -it --rm --volume /var/run/docker.sock:/var/run/docker.sock \
-                        quay.io/vektorlab/ctop:latest")
-
+                    image: String::from("quay.io/vektorlab/ctop:latest"),
+                    volumes: strings(&["/var/run/docker.sock:/var/run/docker.sock"]),
+                    // ctop a besoin d'un terminal : docker attach ots_ctop
+                    extra_args: strings(&["-t"]),
+                    ..Default::default()
                 },
                 Ots {
                     name: String::from("doku"),
-                    port: 25004,
-                    command_line: String::from(
-                        "docker run -d \
-                        -it \
-                        -v /var/run/docker.sock:/var/run/docker.sock:ro \
-                        -v /:/hostroot:ro \
-                        --name ots_doku \
-                        -p 25004:9090 \
-                        --restart unless-stopped \
-                        amerkurev/doku")
+                    image: String::from("amerkurev/doku"),
+                    ports: strings(&["25004:9090"]),
+                    volumes: strings(&["/var/run/docker.sock:/var/run/docker.sock:ro", "/:/hostroot:ro"]),
+                    restart: Some(String::from("unless-stopped")),
+                    ..Default::default()
                 },
                 Ots {
                     name: String::from("dozzle"),
-                    port: 25006,
-                    command_line: String::from(
-                        "docker run -d \
-                        -v /var/run/docker.sock:/var/run/docker.sock \
-                        -e DOZZLE_LEVEL=Debug \
-                        --name ots_doku \
-                        -p 25006:8080 \
-                        --restart unless-stopped \
-                        amir20/dozzle")
-                }
+                    image: String::from("amir20/dozzle"),
+                    ports: strings(&["25006:8080"]),
+                    env: BTreeMap::from([(String::from("DOZZLE_LEVEL"), String::from("Debug"))]),
+                    volumes: strings(&["/var/run/docker.sock:/var/run/docker.sock"]),
+                    restart: Some(String::from("unless-stopped")),
+                    ..Default::default()
+                },
             ],
-        };
-        
-        match default_config.save_to_file() {
-            Ok(_) => {},
-            Err(e) => 
-            eprintln!("Error while saving the default configuration: {}", e),
+            profiles: BTreeMap::from([(
+                String::from("monitoring"),
+                strings(&["dozzle", "doku", "ctop"]),
+            )]),
+            version: CONFIG_VERSION,
+            ..Default::default()
         }
+    }
 
-        default_config
+    /// The typed definition of the service `name`, its variables replaced
+    pub fn service(&self, name: &str) -> Result<Ots, String> {
+        self.find(name)?.interpolated(&|v| self.variables.get(v), None)?.resolved()
+    }
+
+    /// The typed definition of the service `name` to show: the secrets masked,
+    /// the variables not set shown as `<unset>`
+    pub fn masked_service(&self, name: &str) -> Result<Ots, String> {
+        self.find(name)?.interpolated(&|v| self.variables.get_masked(v), Some(variables::UNSET))?.resolved()
+    }
+
+    fn find(&self, name: &str) -> Result<&Ots, String> {
+        self.ots
+            .iter()
+            .find(|o| o.name == name)
+            .ok_or_else(|| format!("OTS {} not found", name))
+    }
+
+    /// Add a service, its name must be new and usable in a container name
+    pub fn add(&mut self, ots: Ots) -> Result<(), String> {
+        check_name(&ots.name)?;
+        if self.ots.iter().any(|o| o.name == ots.name) {
+            return Err(format!("OTS {} already exists", ots.name));
+        }
+        self.ots.push(ots);
+        Ok(())
+    }
+
+    /// Remove services and their profile memberships, a service still
+    /// needed by another one is kept
+    pub fn remove(&mut self, names: &[String]) -> Result<(), String> {
+        for name in names {
+            self.find(name)?;
+            if let Some(dependent) = self
+                .ots
+                .iter()
+                .find(|o| !names.contains(&o.name) && o.depends_on.contains(name))
+            {
+                return Err(format!("OTS {} is needed by {}", name, dependent.name));
+            }
+        }
+        self.ots.retain(|o| !names.contains(&o.name));
+        for services in self.profiles.values_mut() {
+            services.retain(|s| !names.contains(s));
+        }
+        self.profiles.retain(|_, services| !services.is_empty());
+        Ok(())
+    }
+
+    /// Rename a service, the dependencies and the profiles follow
+    pub fn rename(&mut self, old: &str, new: &str) -> Result<(), String> {
+        check_name(new)?;
+        self.find(old)?;
+        if self.ots.iter().any(|o| o.name == new) {
+            return Err(format!("OTS {} already exists", new));
+        }
+        for ots in &mut self.ots {
+            if ots.name == old {
+                ots.name = new.to_string();
+            }
+            for dependency in ots.depends_on.iter_mut().filter(|d| *d == old) {
+                *dependency = new.to_string();
+            }
+        }
+        for service in self.profiles.values_mut().flatten().filter(|s| *s == old) {
+            *service = new.to_string();
+        }
+        Ok(())
     }
 
-    /// Get the full path to the configuration file
+    /// Replace the profiles (`@name`) by their services, without duplicates
+    pub fn expand(&self, names: &[String]) -> Result<Vec<String>, String> {
+        let mut expanded: Vec<String> = Vec::new();
+        for name in names {
+            let services = match name.strip_prefix('@') {
+                Some(profile) => self
+                    .profiles
+                    .get(profile)
+                    .ok_or_else(|| format!("Profile {} not found", profile))?
+                    .clone(),
+                None => vec![name.clone()],
+            };
+            for service in services {
+                if !expanded.contains(&service) {
+                    expanded.push(service);
+                }
+            }
+        }
+        Ok(expanded)
+    }
+
+    /// The services to start for `names` (services and profiles), each after its dependencies
+    pub fn start_order(&self, names: &[String]) -> Result<Vec<String>, String> {
+        let mut order = Vec::new();
+        for name in self.expand(names)? {
+            self.visit(&name, &mut Vec::new(), &mut order)?;
+        }
+        Ok(order)
+    }
+
+    /// Depth first visit of the dependencies, `path` holds the services being visited
+    fn visit(&self, name: &str, path: &mut Vec<String>, order: &mut Vec<String>) -> Result<(), String> {
+        if order.iter().any(|n| n == name) {
+            return Ok(());
+        }
+        if let Some(start) = path.iter().position(|n| n == name) {
+            let mut cycle = path[start..].to_vec();
+            cycle.push(name.to_string());
+            return Err(format!("Dependency cycle: {}", cycle.join(" -> ")));
+        }
+        let ots = self.ots.iter().find(|o| o.name == name).ok_or_else(|| match path.last() {
+            Some(parent) => format!("Unknown dependency '{}' of OTS {}", name, parent),
+            None => format!("OTS {} not found", name),
+        })?;
+        path.push(name.to_string());
+        for dependency in &ots.depends_on {
+            self.visit(dependency, path, order)?;
+        }
+        path.pop();
+        order.push(name.to_string());
+        Ok(())
+    }
+
+    /// Check the configuration and return the problems found
     ///
-    /// This method constructs the path to the configuration file in the user's home directory.
+    /// Names, container names and host ports must be unique, the containers
+    /// must follow the `ots_<name>` convention, command lines must parse and
+    /// images must be valid references. Aliases must not replace a dk command
+    /// nor expand to themselves.
+    pub fn check(&self) -> Vec<ConfigProblem> {
+        let mut problems = Vec::new();
+        let mut add = |path: String, message: String| problems.push(ConfigProblem { path, message });
+        for key in self.unknown.keys() {
+            add(format!("$.{}", key), String::from("unknown field"));
+        }
 
-    fn get_config_path() -> PathBuf {
-        if let Some(home_dir) = dirs::home_dir() {
-            let full_path: PathBuf = home_dir.join(CONFIG_DIRECTORY);
-            Self::ensure_config_directory_exists(&full_path);
-            full_path.join(CONFIG_FILE_NAME)
-        } else {
-            panic!("Unable to retrieve home directory!");
+        for (profile, services) in &self.profiles {
+            for (k, service) in services.iter().enumerate() {
+                if !self.ots.iter().any(|o| o.name == *service) {
+                    add(format!("$.profiles.{}[{}]", profile, k), format!("unknown service '{}'", service));
+                }
+            }
         }
+
+        let mut in_cycle: Vec<String> = Vec::new();
+        let mut names: HashMap<&str, usize> = HashMap::new();
+        let mut containers: HashMap<String, usize> = HashMap::new();
+        let mut host_ports: HashMap<(u16, String), usize> = HashMap::new();
+        for (i, o) in self.ots.iter().enumerate() {
+            let at = |field: &str| {
+                // les champs d'une entrée historique viennent tous de sa ligne de commande
+                let field = if o.is_legacy() && field != "name" { "command_line" } else { field };
+                format!("$.ots[{}].{}", i, field)
+            };
+            for key in o.unknown.keys() {
+                let message = match key.as_str() {
+                    "port" => String::from("'port' is no longer used, the URL comes from 'url' or 'ports'"),
+                    _ => String::from("unknown field"),
+                };
+                add(format!("$.ots[{}].{}", i, key), message);
+            }
+
+            if o.name.is_empty() {
+                add(at("name"), String::from("empty name"));
+            } else if let Some(j) = names.get(o.name.as_str()) {
+                add(at("name"), format!("duplicate name '{}' (see $.ots[{}])", o.name, j));
+            } else {
+                names.insert(&o.name, i);
+            }
+
+            for (k, dependency) in o.depends_on.iter().enumerate() {
+                if !self.ots.iter().any(|d| d.name == *dependency) {
+                    add(format!("$.ots[{}].depends_on[{}]", i, k), format!("unknown service '{}'", dependency));
+                }
+            }
+            if let Err(e) = self.visit(&o.name, &mut Vec::new(), &mut Vec::new()) {
+                // un cycle est signalé une seule fois, sur la première entrée qui en fait partie
+                if let Some(cycle) = e.strip_prefix("Dependency cycle: ") {
+                    let members: Vec<&str> = cycle.split(" -> ").collect();
+                    if members.contains(&o.name.as_str()) && !in_cycle.contains(&o.name) {
+                        add(format!("$.ots[{}].depends_on", i), e.clone());
+                        in_cycle.extend(members.iter().map(|m| m.to_string()));
+                    }
+                }
+            }
+
+            // une variable absente n'est pas une erreur de la configuration
+            let ots = match o.interpolated(&|v| self.variables.get(v), Some("")).and_then(|o| o.resolved()) {
+                Ok(ots) => ots,
+                Err(e) => {
+                    add(at("command_line"), e);
+                    continue;
+                }
+            };
+
+            if ots.image.is_empty() {
+                add(at("image"), String::from("no image"));
+            } else if let Err(e) = ImageRef::parse(&ots.image) {
+                add(at("image"), e);
+            }
+
+            let container = ots.container_name();
+            let expected = format!("ots_{}", ots.name);
+            if container != expected {
+                add(
+                    at("container_name"),
+                    format!("container name '{}' does not follow the ots_<name> convention ('{}')", container, expected),
+                );
+            }
+            if let Some(j) = containers.get(&container) {
+                add(at("container_name"), format!("container name '{}' already used by $.ots[{}]", container, j));
+            } else {
+                containers.insert(container, i);
+            }
+
+            for (k, port) in ots.ports.iter().enumerate() {
+                match parse_port(port) {
+                    Err(e) => add(at(&format!("ports[{}]", k)), e),
+                    Ok(Some(key)) => {
+                        if let Some(j) = host_ports.get(&key) {
+                            add(at(&format!("ports[{}]", k)), format!("host port {} already published by $.ots[{}]", key.0, j));
+                        } else {
+                            host_ports.insert(key, i);
+                        }
+                    }
+                    Ok(None) => {}
+                }
+            }
+
+            match &o.ready {
+                Some(Probe::Tcp { port: 0 }) => add(format!("$.ots[{}].ready.port", i), String::from("invalid port 0")),
+                Some(Probe::Http { url, .. }) if !url.starts_with("http://") => add(
+                    format!("$.ots[{}].ready.url", i),
+                    format!("'{}' is not an http:// URL", url),
+                ),
+                _ => {}
+            }
+            if let Some(url) = o.url.as_ref().filter(|u| !u.starts_with("http://") && !u.starts_with("https://")) {
+                add(format!("$.ots[{}].url", i), format!("'{}' is not an http(s) URL", url));
+            }
+
+            for arg in &ots.extra_args {
+                let interactive = arg == "--interactive"
+                    || (!arg.starts_with("--") && arg.starts_with('-') && arg.contains('i'));
+                if interactive {
+                    add(at("extra_args"), format!("'{}' is useless, dk runs the services detached (-d)", arg));
+                } else if arg == "--rm" {
+                    add(at("extra_args"), String::from("'--rm' deletes the container of the service when it stops"));
+                }
+            }
+        }
+        problems.extend(alias_helper::problems(self));
+        problems
+    }
+
+    /// Directory of the configuration, also holding `.env` and `secrets.env`
+    pub fn get_config_dir() -> io::Result<PathBuf> {
+        Ok(Self::get_config_path()?.parent().map(PathBuf::from).unwrap_or_default())
     }
 
-/// Ensure the configuration directory exists
+    /// Get the full path to the global configuration file
+    ///
+    /// `DK_CONFIG` gives the file, otherwise the `dk_config.json` (or `.toml`,
+    /// `.yaml`) of `~/.dk`.
+    pub fn get_config_path() -> io::Result<PathBuf> {
+        if let Some(path) = env::var_os("DK_CONFIG").filter(|p| !p.is_empty()) {
+            return Ok(PathBuf::from(path));
+        }
+        match dirs::home_dir() {
+            Some(home_dir) => {
+                let dir = home_dir.join(CONFIG_DIRECTORY);
+                Ok(find_config_file(&dir).unwrap_or_else(|| dir.join(CONFIG_FILE_NAME)))
+            }
+            None => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "Unable to retrieve home directory, set DK_CONFIG",
+            )),
+        }
+    }
+
+    /// Ensure the configuration directory exists
     ///
     /// This method creates the configuration directory if it does not already exist.
-        fn ensure_config_directory_exists(config_dir: &PathBuf) {
+    fn ensure_config_directory_exists(config_dir: &Path) -> io::Result<()> {
         if !config_dir.exists() {
-            fs::create_dir_all(config_dir).expect("unable to create config directory");
+            fs::create_dir_all(config_dir)?;
         }
+        Ok(())
     }
 
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn legacy(line: &str) -> Ots {
+        Ots { name: String::from("app"), command_line: Some(String::from(line)), ..Default::default() }
+    }
+
+    #[test]
+    fn test_legacy_command_line() {
+        let ots = legacy(
+            "docker run -d --name=ots_app -p 8080:80 -e 'GREETING=hello world' \\\n \
+             -it --network host --mount type=bind,src=/tmp,dst=/data --restart always \
+             nginx:1.27 nginx -g \"daemon off;\"",
+        );
+        let args = ots.docker_args().unwrap();
+        assert_eq!(
+            args,
+            strings(&[
+                "docker", "run", "-d", "--name", "ots_app", "-p", "8080:80", "-e", "GREETING=hello world",
+                "--mount", "type=bind,src=/tmp,dst=/data", "--restart", "always", "-it", "--network", "host",
+                "nginx:1.27", "nginx", "-g", "daemon off;",
+            ])
+        );
+        assert_eq!(ots.resolved().unwrap().host_port(), Some(8080));
+    }
+
+    #[test]
+    fn test_invalid_command_line() {
+        assert!(legacy("docker run -d --name ots_app").docker_args().is_err());
+        assert!(legacy("podman run nginx").docker_args().is_err());
+        assert!(legacy("docker run -e 'A=b nginx").docker_args().is_err());
+    }
+
+    #[test]
+    fn test_check() {
+        let json = r#"{
+            "ots": [
+                { "name": "web", "image": "nginx:1.27", "ports": ["8080:80", "127.0.0.1:9000:90/udp"] },
+                { "name": "doku", "port": 25004, "command_line": "docker run -d --name ots_web -p 8080:80 -it amerkurev/doku" },
+                { "name": "web", "image": "Nginx", "ports": ["80", "http"], "extra_args": ["--rm"] },
+                { "name": "bad", "command_line": "docker run -d 'oops" },
+                { "name": "api", "image": "api", "ready": { "type": "http", "url": "localhost:8000" }, "url": "localhost:8000" }
+            ],
+            "theme": "dark"
+        }"#;
+        let config: DkConfig = serde_json::from_str(json).unwrap();
+        let problems: Vec<String> = config.check().iter().map(|p| p.to_string()).collect();
+        assert_eq!(
+            problems,
+            vec![
+                "$.theme: unknown field",
+                "$.ots[1].port: 'port' is no longer used, the URL comes from 'url' or 'ports'",
+                "$.ots[1].command_line: container name 'ots_web' does not follow the ots_<name> convention ('ots_doku')",
+                "$.ots[1].command_line: container name 'ots_web' already used by $.ots[0]",
+                "$.ots[1].command_line: host port 8080 already published by $.ots[0]",
+                "$.ots[1].command_line: '-it' is useless, dk runs the services detached (-d)",
+                "$.ots[2].name: duplicate name 'web' (see $.ots[0])",
+                "$.ots[2].image: invalid image reference 'Nginx'",
+                "$.ots[2].container_name: container name 'ots_web' already used by $.ots[0]",
+                "$.ots[2].ports[1]: invalid published port 'http'",
+                "$.ots[2].extra_args: '--rm' deletes the container of the service when it stops",
+                "$.ots[3].command_line: Unterminated quote in: docker run -d 'oops",
+                "$.ots[4].ready.url: 'localhost:8000' is not an http:// URL",
+                "$.ots[4].url: 'localhost:8000' is not an http(s) URL",
+            ]
+        );
+    }
+
+    fn dependencies() -> DkConfig {
+        serde_json::from_str(
+            r#"{
+                "ots": [
+                    { "name": "app", "image": "app", "depends_on": ["db", "broker"] },
+                    { "name": "db", "image": "postgres" },
+                    { "name": "broker", "image": "rabbitmq", "depends_on": ["db"] },
+                    { "name": "web", "image": "nginx", "depends_on": ["app"] }
+                ],
+                "profiles": { "backend": ["broker", "db"], "all": ["web", "db"] }
+            }"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_start_order() {
+        let config = dependencies();
+        let names = |names: &[&str]| strings(names);
+        assert_eq!(config.start_order(&names(&["app"])).unwrap(), names(&["db", "broker", "app"]));
+        assert_eq!(config.start_order(&names(&["@all"])).unwrap(), names(&["db", "broker", "app", "web"]));
+        assert_eq!(config.start_order(&names(&["@backend", "db"])).unwrap(), names(&["db", "broker"]));
+        assert_eq!(config.start_order(&names(&["@front"])), Err(String::from("Profile front not found")));
+        assert_eq!(config.start_order(&names(&["cache"])), Err(String::from("OTS cache not found")));
+        assert_eq!(config.check(), vec![]);
+    }
+
+    #[test]
+    fn test_add_remove_rename() {
+        let mut config = dependencies();
+        let cache = Ots { name: String::from("cache"), image: String::from("redis"), ..Default::default() };
+        assert_eq!(config.add(cache.clone()), Ok(()));
+        assert_eq!(config.add(cache), Err(String::from("OTS cache already exists")));
+        let invalid = Ots { name: String::from("-x"), image: String::from("redis"), ..Default::default() };
+        assert!(config.add(invalid).is_err());
+
+        assert_eq!(config.rename("db", "postgres"), Ok(()));
+        assert_eq!(config.ots[0].depends_on, strings(&["postgres", "broker"]));
+        assert_eq!(config.profiles["backend"], strings(&["broker", "postgres"]));
+        assert_eq!(config.rename("app", "web"), Err(String::from("OTS web already exists")));
+        assert_eq!(config.rename("db", "x"), Err(String::from("OTS db not found")));
+
+        assert_eq!(config.remove(&strings(&["postgres"])), Err(String::from("OTS postgres is needed by app")));
+        assert_eq!(config.remove(&strings(&["web", "postgres", "app", "broker"])), Ok(()));
+        assert_eq!(config.ots.len(), 1);
+        assert!(config.profiles.is_empty());
+        assert_eq!(config.check(), vec![]);
+    }
+
+    #[test]
+    fn test_read_errors() {
+        let dir = std::env::temp_dir().join(format!("dk_config_errors_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("dk_config.json");
+        let error = DkConfig::read_file(&path).unwrap_err();
+        assert!(error.is_missing());
+        assert!(error.to_string().ends_with("dk_config.json not found (dk config init creates it)"));
+
+        fs::write(&path, "{\n  \"ots\": [\n    { \"name\": \"web\", \"image\": \"nginx\" },\n  ]\n}").unwrap();
+        match DkConfig::read_file(&path).unwrap_err() {
+            ConfigError::Parse { line, column, message, .. } => {
+                assert_eq!((line, column), (4, 3));
+                assert_eq!(message, "trailing comma");
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        let denied = ConfigError::from_io(&path, io::Error::from(io::ErrorKind::PermissionDenied));
+        assert!(matches!(denied, ConfigError::Permission(_)));
+        assert!(!denied.is_missing());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_migrate() {
+        let mut document = serde_json::json!({
+            "ots": [
+                { "name": "kroki", "port": 25100, "command_line": "docker run -d --name ots_kroki -p 25100:8000 --restart unless-stopped yuzutech/kroki" },
+                { "name": "web", "port": 8080, "command_line": "docker run nginx'", "depends_on": ["kroki"] },
+                { "name": "db", "image": "postgres:17" },
+                { "name": "portainer", "port": 25003, "command_line": "docker run -d -p 9000:9000 -p 25003:9443 portainer/portainer-ce" },
+                { "name": "sqlserver", "port": 1433, "command_line": "docker run -d -p 1433:1433 mcr.microsoft.com/mssql/server" },
+                { "name": "ctop", "port": 0, "command_line": "docker run -d quay.io/vektorlab/ctop" }
+            ]
+        });
+        assert_eq!(migrate(&mut document), Ok(1));
+        assert_eq!(
+            document["ots"][0],
+            serde_json::json!({ "name": "kroki", "image": "yuzutech/kroki", "ports": ["25100:8000"], "restart": "unless-stopped" })
+        );
+        // une ligne invalide est gardée telle quelle, la vérification la signale
+        assert_eq!(document["ots"][1]["command_line"], "docker run nginx'");
+        assert!(document["ots"][1].get("port").is_none());
+        assert_eq!(document["ots"][1]["url"], "http://localhost:8080");
+        // le port de la v1 est gardé quand il n'est pas celui du premier port web
+        assert_eq!(document["ots"][3]["url"], "https://localhost:25003");
+        let portainer: Ots = serde_json::from_value(document["ots"][3].clone()).unwrap();
+        assert_eq!(portainer.status_url().as_deref(), Some("https://localhost:25003"));
+        assert!(document["ots"][4].get("url").is_none());
+        assert!(document["ots"][5].get("url").is_none());
+        assert_eq!(document["version"], CONFIG_VERSION);
+        assert_eq!(migrate(&mut document), Ok(CONFIG_VERSION));
+
+        assert!(migrate(&mut serde_json::json!({ "version": 99, "ots": [] })).unwrap_err().contains("newer dk"));
+        assert!(migrate(&mut serde_json::json!({ "version": "two", "ots": [] })).is_err());
+    }
+
+    #[test]
+    fn test_read_migrates_file() {
+        let dir = std::env::temp_dir().join(format!("dk_config_migrate_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("dk_config.json");
+        let original = r#"{ "ots": [{ "name": "web", "port": 8080, "command_line": "docker run -d -p 8080:80 nginx" }] }"#;
+        fs::write(&path, original).unwrap();
+
+        // la lecture migre en mémoire sans toucher au fichier
+        let config = DkConfig::read_file(&path).unwrap();
+        assert_eq!(config.version, 1);
+        assert_eq!(config.ots[0].ports, strings(&["8080:80"]));
+        assert_eq!(fs::read_to_string(&path).unwrap(), original);
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+        // la sauvegarde écrit la version courante et garde l'original
+        config.save_to(&path).unwrap();
+        assert_eq!(fs::read_to_string(dir.join("dk_config.json.v1.bak")).unwrap(), original);
+        assert_eq!(DkConfig::stored_version(&path), Some(CONFIG_VERSION));
+        let saved = DkConfig::read_file(&path).unwrap();
+        assert_eq!((saved.version, saved.ots), (CONFIG_VERSION, config.ots));
+
+        fs::write(&path, r#"{ "version": 3, "ots": [] }"#).unwrap();
+        assert!(matches!(DkConfig::read_file(&path), Err(ConfigError::Invalid { .. })));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_save_keeps_backup() {
+        let dir = std::env::temp_dir().join(format!("dk_config_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("dk_config.json");
+        let mut config = dependencies();
+        config.save_to(&path).unwrap();
+        assert!(!dir.join("dk_config.json.bak").exists());
+        config.remove(&strings(&["web"])).unwrap();
+        config.save_to(&path).unwrap();
+
+        let saved: DkConfig = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        let backup: DkConfig = serde_json::from_str(&fs::read_to_string(dir.join("dk_config.json.bak")).unwrap()).unwrap();
+        assert_eq!(saved.ots.len(), 3);
+        assert_eq!(backup.ots.len(), 4);
+        assert!(!dir.join("dk_config.json.tmp").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_dependency_errors() {
+        let mut config = dependencies();
+        config.ots[1].depends_on = strings(&["web"]);
+        config.ots[2].depends_on.push(String::from("cache"));
+        config.profiles.insert(String::from("tools"), strings(&["ctop"]));
+        assert_eq!(
+            config.start_order(&strings(&["web"])),
+            Err(String::from("Dependency cycle: web -> app -> db -> web"))
+        );
+        assert_eq!(
+            config.start_order(&strings(&["broker"])),
+            Err(String::from("Dependency cycle: db -> web -> app -> db"))
+        );
+        let problems: Vec<String> = config.check().iter().map(|p| p.to_string()).collect();
+        assert_eq!(
+            problems,
+            vec![
+                "$.profiles.tools[0]: unknown service 'ctop'",
+                "$.ots[0].depends_on: Dependency cycle: app -> db -> web -> app",
+                "$.ots[2].depends_on[1]: unknown service 'cache'",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_variables() {
+        let mut config: DkConfig = serde_json::from_str(
+            r#"{ "ots": [
+                { "name": "db", "image": "postgres:${DK_TEST_PG:-16}", "ports": ["${DK_TEST_DB_PORT}:5432"],
+                  "env": { "POSTGRES_PASSWORD": "${DK_TEST_DB_PASSWORD}" } },
+                { "name": "old", "command_line": "docker run -d --name ots_old -e 'TOKEN=${DK_TEST_DB_PASSWORD}' alpine:${DK_TEST_PG" }
+            ] }"#,
+        )
+        .unwrap();
+        assert_eq!(config.service("db"), Err(String::from("OTS db: Variable DK_TEST_DB_PORT is not set (environment, .env or secrets.env)")));
+
+        config.variables = Variables::with_values(
+            BTreeMap::from([(String::from("DK_TEST_DB_PORT"), String::from("15432"))]),
+            BTreeMap::from([(String::from("DK_TEST_DB_PASSWORD"), String::from("p@ss word"))]),
+        );
+        let db = config.service("db").unwrap();
+        assert_eq!(db.image, "postgres:16");
+        assert_eq!(db.host_port(), Some(15432));
+        assert!(db.docker_args().unwrap().contains(&String::from("POSTGRES_PASSWORD=p@ss word")));
+        assert_eq!(config.masked_service("db").unwrap().env["POSTGRES_PASSWORD"], "******");
+
+        let problems: Vec<String> = config.check().iter().map(|p| p.to_string()).collect();
+        assert_eq!(
+            problems,
+            vec!["$.ots[1].command_line: OTS old: Unterminated variable in 'docker run -d --name ots_old -e 'TOKEN=${DK_TEST_DB_PASSWORD}' alpine:${DK_TEST_PG'"]
+        );
+    }
+
+    #[test]
+    fn test_default_config() {
+        let config = DkConfig::default_config();
+        let excalidraw = config.ots.iter().find(|o| o.name == "excalidraw").unwrap();
+        assert_eq!(excalidraw.host_port(), Some(25000));
+        assert_eq!(excalidraw.container_name(), "ots_excalidraw");
+        let volumes: Vec<Vec<String>> = config.ots.iter().map(|o| o.named_volumes()).collect();
+        assert_eq!(volumes[0], vec!["portainer_data"]);
+        assert_eq!(volumes[4], vec!["ots_rabbitmq"]);
+        assert!(volumes[6].is_empty());
+        let json = serde_json::to_string(&config).unwrap();
+        let parsed: DkConfig = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.ots, config.ots);
+        assert_eq!(config.check(), vec![]);
+    }
+}
@@ -0,0 +1,104 @@
+use clap::ValueEnum;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use std::path::Path;
+
+/// Formats of the configuration file, chosen by its extension
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum ConfigFormat {
+    Json,
+    Toml,
+    Yaml,
+}
+
+/// A parse error, located when the format gives the position (1-based)
+#[derive(Debug, PartialEq)]
+pub struct FormatError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl ConfigFormat {
+    /// Format of a file: `.toml`, `.yaml` or `.yml`, JSON otherwise
+    pub fn from_path(path: &Path) -> ConfigFormat {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("toml") => ConfigFormat::Toml,
+            Some("yaml" | "yml") => ConfigFormat::Yaml,
+            _ => ConfigFormat::Json,
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            ConfigFormat::Json => "json",
+            ConfigFormat::Toml => "toml",
+            ConfigFormat::Yaml => "yaml",
+        }
+    }
+
+    pub fn parse<T: DeserializeOwned>(self, text: &str) -> Result<T, FormatError> {
+        match self {
+            ConfigFormat::Json => serde_json::from_str(text).map_err(|e| {
+                let message = e.to_string();
+                // le message de serde_json se termine par la position, donnée à part
+                let message = message.rsplit_once(" at line ").map(|(m, _)| m.to_string()).unwrap_or(message);
+                FormatError { line: e.line(), column: e.column(), message }
+            }),
+            ConfigFormat::Toml => toml::from_str(text).map_err(|e| {
+                let (line, column) = e
+                    .span()
+                    .map(|span| position(text, span.start))
+                    .unwrap_or_default();
+                FormatError { line, column, message: e.message().trim().to_string() }
+            }),
+            ConfigFormat::Yaml => serde_yaml::from_str(text).map_err(|e| {
+                let (line, column) = e.location().map(|l| (l.line(), l.column())).unwrap_or_default();
+                let message = e.to_string();
+                let message = message.rsplit_once(" at line ").map(|(m, _)| m.to_string()).unwrap_or(message);
+                FormatError { line, column, message }
+            }),
+        }
+    }
+
+    pub fn serialize<T: Serialize>(self, value: &T) -> Result<String, String> {
+        match self {
+            ConfigFormat::Json => serde_json::to_string_pretty(value).map_err(|e| e.to_string()),
+            ConfigFormat::Toml => toml::to_string_pretty(value).map_err(|e| e.to_string()),
+            ConfigFormat::Yaml => serde_yaml::to_string(value).map_err(|e| e.to_string()),
+        }
+    }
+}
+
+/// Line and column (1-based) of a byte offset
+fn position(text: &str, offset: usize) -> (usize, usize) {
+    let before = &text[..offset.min(text.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before.len() - before.rfind('\n').map(|i| i + 1).unwrap_or(0) + 1;
+    (line, column)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    #[test]
+    fn test_from_path() {
+        assert_eq!(ConfigFormat::from_path(Path::new("/home/me/.dk/dk_config.toml")), ConfigFormat::Toml);
+        assert_eq!(ConfigFormat::from_path(Path::new("dk_config.yml")), ConfigFormat::Yaml);
+        assert_eq!(ConfigFormat::from_path(Path::new("dk_config.json")), ConfigFormat::Json);
+        assert_eq!(ConfigFormat::from_path(Path::new("dk_config")), ConfigFormat::Json);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let error = ConfigFormat::Toml.parse::<Value>("version = 2\n[[ots]]\nname = \"web\nimage = \"nginx\"\n").unwrap_err();
+        assert_eq!((error.line, error.column), (3, 12));
+        let error = ConfigFormat::Yaml.parse::<Value>("ots:\n  - name: web\n   image: nginx\n").unwrap_err();
+        assert_eq!(error.line, 3);
+        let error = ConfigFormat::Json.parse::<Value>("{\n  \"ots\": [,]\n}").unwrap_err();
+        assert_eq!((error.line, error.column, error.message.as_str()), (2, 11, "expected value"));
+    }
+}
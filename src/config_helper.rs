@@ -0,0 +1,333 @@
+use prettytable::{Cell, Row};
+use serde_json::Value;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::cli::{self, ConfigCommand};
+use crate::config::{sibling, DkConfig, CONFIG_VERSION};
+use crate::config_format::ConfigFormat;
+use crate::config_layers::{self, LayeredConfig};
+use crate::dkutil::*;
+use crate::variables::SECRETS_FILE_NAME;
+
+/// JSON Schema of the configuration, written next to it by `dk config init`
+const SCHEMA: &str = include_str!("../schema/dk_config.schema.json");
+const SCHEMA_FILE_NAME: &str = "dk_config.schema.json";
+
+pub fn usage() {
+    cli::print_usage_section("CONFIG");
+}
+
+pub fn cmd(command: Option<ConfigCommand>) -> i32 {
+    match command {
+        None => {
+            usage();
+            0
+        }
+        Some(ConfigCommand::Init { force }) => match DkConfig::get_config_path() {
+            Ok(path) => init(&path, force),
+            Err(e) => {
+                print_error(&e.to_string());
+                1
+            }
+        },
+        Some(ConfigCommand::Convert { to, file }) => {
+            match file.map(PathBuf::from).map(Ok).unwrap_or_else(DkConfig::get_config_path) {
+                Ok(path) => convert(&path, to),
+                Err(e) => {
+                    print_error(&e.to_string());
+                    1
+                }
+            }
+        }
+        Some(ConfigCommand::Migrate { file }) => {
+            match file.map(PathBuf::from).map(Ok).unwrap_or_else(DkConfig::get_config_path) {
+                Ok(path) => migrate(&path),
+                Err(e) => {
+                    print_error(&e.to_string());
+                    1
+                }
+            }
+        }
+        Some(ConfigCommand::Schema) => {
+            print!("{}", SCHEMA);
+            0
+        }
+        Some(ConfigCommand::Show { origin }) => {
+            match config_layers::load_config() {
+                Ok(layered) => {
+                    show(&layered, origin);
+                    0
+                }
+                Err(e) => {
+                    print_error(&e);
+                    1
+                }
+            }
+        }
+    }
+}
+
+/// Write the default configuration to `path`, an existing file is only replaced with `force`
+fn init(path: &Path, force: bool) -> i32 {
+    if path.exists() && !force {
+        print_error(&format!("{} already exists (--force replaces it, keeping a backup)", path.display()));
+        return 1;
+    }
+    let schema = path.with_file_name(SCHEMA_FILE_NAME);
+    let config = DkConfig { schema: Some(format!("./{}", SCHEMA_FILE_NAME)), ..DkConfig::default_config() };
+    match config.save_to(path).and_then(|_| fs::write(&schema, SCHEMA)) {
+        Ok(()) => {
+            print_info(&format!("Default configuration (version {}) written to {}", CONFIG_VERSION, path.display()));
+            // le mot de passe de sqlserver n'a pas de valeur par défaut
+            print_info(&format!(
+                "Set the secrets such as SA_PASSWORD (sqlserver) in {} (chmod 600)",
+                path.with_file_name(SECRETS_FILE_NAME).display()
+            ));
+            0
+        }
+        Err(e) => {
+            print_error(&format!("Unable to write {} : {}", path.display(), e));
+            1
+        }
+    }
+}
+
+/// Write a configuration file of a previous version in the current one,
+/// the original being kept as `<file>.v<version>.bak`
+fn migrate(path: &Path) -> i32 {
+    let config = match DkConfig::read_file(path) {
+        Ok(config) => config,
+        Err(e) => {
+            print_error(&format!("Invalid configuration {}", e));
+            return 1;
+        }
+    };
+    if config.version == CONFIG_VERSION {
+        print_info(&format!("{} is already a version {} configuration", path.display(), CONFIG_VERSION));
+        return 0;
+    }
+    match config.save_to(path) {
+        Ok(()) => 0,
+        Err(e) => {
+            print_error(&format!("Unable to write {} : {}", path.display(), e));
+            1
+        }
+    }
+}
+
+/// Convert a configuration file to the format `to`, the converted file is read
+/// back and compared before the original is moved to `<file>.bak`
+fn convert(path: &Path, to: ConfigFormat) -> i32 {
+    let target = path.with_extension(to.extension());
+    let converted = if ConfigFormat::from_path(path) == to {
+        Err(format!("{} is already a {} file", path.display(), to.extension()))
+    } else if target.exists() {
+        Err(format!("{} already exists", target.display()))
+    } else {
+        DkConfig::read_file(path)
+            .map_err(|e| format!("Invalid configuration {}", e))
+            .and_then(|config| {
+                config
+                    .save_to(&target)
+                    .map_err(|e| format!("Unable to write {} : {}", target.display(), e))?;
+                // la cible est écrite dans la version courante
+                let current = DkConfig { version: CONFIG_VERSION, ..config };
+                let same = DkConfig::read_file(&target)
+                    .is_ok_and(|c| serde_json::to_value(&c).ok() == serde_json::to_value(&current).ok());
+                if !same {
+                    let _ = fs::remove_file(&target);
+                    return Err(format!("The conversion to {} would lose data, {} is kept", to.extension(), path.display()));
+                }
+                fs::rename(path, sibling(path, "bak")).map_err(|e| e.to_string())
+            })
+    };
+    if let Err(e) = converted {
+        print_error(&e);
+        return 1;
+    }
+    print_info(&format!(
+        "{} converted to {} (original kept in {})",
+        path.display(),
+        target.display(),
+        sibling(path, "bak").display()
+    ));
+    if env::var_os("DK_CONFIG").is_some_and(|p| Path::new(&p) == path) {
+        print_warning(&format!("DK_CONFIG must now be {}", target.display()));
+    }
+    0
+}
+
+fn show(layered: &LayeredConfig, origin: bool) {
+    if !origin {
+        println!("{}", serde_json::to_string_pretty(&layered.config).unwrap_or_default());
+        return;
+    }
+    let files: Vec<String> = layered.files.iter().map(|f| f.display().to_string()).collect();
+    print_colored(&format!("(y)Files (w): {}", files.join(", ")));
+    let mut table = new_table(&["Key", "Value", "Origin"]);
+    for (key, value, origin) in origin_rows(layered) {
+        table.add_row(Row::new(vec![Cell::new(&key), Cell::new(&value), Cell::new(&origin)]));
+    }
+    print_table(&table);
+}
+
+/// Key, value and origin of each value, in the order of the configuration
+fn origin_rows(layered: &LayeredConfig) -> Vec<(String, String, String)> {
+    let value = serde_json::to_value(&layered.config).unwrap_or_default();
+    let mut keys: Vec<(String, &Value)> = Vec::new();
+    for entry in value["ots"].as_array().into_iter().flatten().filter_map(|e| e.as_object()) {
+        let name = entry.get("name").and_then(|n| n.as_str()).unwrap_or_default();
+        for (field, value) in entry.iter().filter(|(field, _)| *field != "name") {
+            keys.push((format!("ots.{}.{}", name, field), value));
+        }
+    }
+    for section in ["profiles", "aliases"] {
+        for (name, item) in value[section].as_object().into_iter().flatten() {
+            keys.push((format!("{}.{}", section, name), item));
+        }
+    }
+    // la version et le schéma décrivent chaque fichier, pas la configuration
+    for (key, value) in value.as_object().into_iter().flatten() {
+        if !["ots", "profiles", "aliases", "version", "$schema"].contains(&key.as_str()) {
+            keys.push((key.clone(), value));
+        }
+    }
+
+    keys.into_iter()
+        .map(|(key, value)| {
+            let text = match value {
+                Value::String(s) => s.clone(),
+                other => other.to_string(),
+            };
+            let origin = layered.origins.get(&key).cloned().unwrap_or_default();
+            (key, text, origin)
+        })
+        .collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Ots, Probe};
+    use crate::config_layers::{merge, Layer};
+    use serde_json::json;
+
+    #[test]
+    fn test_init() {
+        let dir = std::env::temp_dir().join(format!("dk_config_init_{}", std::process::id()));
+        let path = dir.join(".dk").join("dk_config.json");
+        assert_eq!(init(&path, false), 0);
+        fs::write(&path, "{ \"ots\": [], }").unwrap();
+        assert_eq!(init(&path, false), 1);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "{ \"ots\": [], }");
+        assert_eq!(init(&path, true), 0);
+        assert!(DkConfig::read_file(&path).is_ok());
+        assert_eq!(fs::read_to_string(path.with_extension("json.bak")).unwrap(), "{ \"ots\": [], }");
+        assert_eq!(fs::read_to_string(path.with_file_name(SCHEMA_FILE_NAME)).unwrap(), SCHEMA);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_convert() {
+        let dir = std::env::temp_dir().join(format!("dk_config_convert_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let json = dir.join("dk_config.json");
+        let mut config = DkConfig::default_config();
+        config.ots.push(Ots {
+            name: String::from("legacy"),
+            command_line: Some(String::from("docker run -d \\\n  --name ots_legacy \\\n  -p 8080:80 nginx")),
+            ..Default::default()
+        });
+        config.save_to(&json).unwrap();
+
+        assert_eq!(convert(&json, ConfigFormat::Json), 1);
+        assert_eq!(convert(&json, ConfigFormat::Toml), 0);
+        assert!(!json.exists());
+        let toml = dir.join("dk_config.toml");
+        let converted = DkConfig::read_file(&toml).unwrap();
+        assert_eq!(converted.ots, config.ots);
+        assert_eq!(converted.profiles, config.profiles);
+        assert!(fs::read_to_string(&toml).unwrap().contains("[[ots]]"));
+
+        assert_eq!(convert(&toml, ConfigFormat::Yaml), 0);
+        assert_eq!(DkConfig::read_file(&dir.join("dk_config.yaml")).unwrap().ots, config.ots);
+        assert!(dir.join("dk_config.toml.bak").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_migrate() {
+        let dir = std::env::temp_dir().join(format!("dk_config_migrate_cmd_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("dk_config.yaml");
+        let original = "ots:\n- name: web\n  port: 8080\n  command_line: docker run -d -p 8080:80 nginx\n";
+        fs::write(&path, original).unwrap();
+
+        assert_eq!(migrate(&path), 0);
+        assert_eq!(fs::read_to_string(dir.join("dk_config.yaml.v1.bak")).unwrap(), original);
+        assert_eq!(DkConfig::stored_version(&path), Some(CONFIG_VERSION));
+        assert_eq!(DkConfig::read_file(&path).unwrap().ots[0].image, "nginx");
+        // déjà à jour : rien n'est réécrit
+        let migrated = fs::read_to_string(&path).unwrap();
+        assert_eq!(migrate(&path), 0);
+        assert_eq!(fs::read_to_string(&path).unwrap(), migrated);
+        assert_eq!(migrate(&dir.join("missing.json")), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_schema_matches_config() {
+        let schema: Value = serde_json::from_str(SCHEMA).unwrap();
+        assert_eq!(schema["properties"]["version"]["const"], CONFIG_VERSION);
+        // chaque champ écrit par dk doit être décrit par le schéma
+        let mut config = DkConfig { schema: Some(String::new()), ..DkConfig::default_config() };
+        config.aliases.insert(String::from("clean"), String::from("sys prune && vol prune"));
+        let config = serde_json::to_value(config).unwrap();
+        let fields = schema["properties"].as_object().unwrap();
+        assert!(config.as_object().unwrap().keys().all(|k| fields.contains_key(k)));
+        let ots = Ots {
+            name: String::from("web"),
+            image: String::from("nginx"),
+            container_name: Some(String::from("web")),
+            ports: vec![String::from("80")],
+            env: [(String::from("A"), String::from("1"))].into(),
+            volumes: vec![String::from("v:/v")],
+            mounts: vec![String::from("type=tmpfs,dst=/t")],
+            restart: Some(String::from("always")),
+            extra_args: vec![String::from("--init")],
+            command: vec![String::from("nginx")],
+            depends_on: vec![String::from("db")],
+            ready: Some(Probe::Docker),
+            url: Some(String::from("http://localhost:8080")),
+            command_line: Some(String::from("docker run nginx")),
+            ..Default::default()
+        };
+        let fields = schema["definitions"]["ots"]["properties"].as_object().unwrap();
+        let ots = serde_json::to_value(&ots).unwrap();
+        for key in ots.as_object().unwrap().keys() {
+            assert!(fields.contains_key(key), "{} is not in the schema", key);
+        }
+    }
+
+    #[test]
+    fn test_origin_rows() {
+        let layers = vec![
+            Layer { origin: String::from("global"), value: json!({ "ots": [{ "name": "kroki", "image": "yuzutech/kroki" }] }) },
+            Layer { origin: String::from("project"), value: json!({ "ots": [{ "name": "kroki", "ports": ["8000:8000"] }], "profiles": { "docs": ["kroki"] } }) },
+        ];
+        let (value, origins) = merge(&layers);
+        let layered = LayeredConfig { config: serde_json::from_value(value).unwrap(), origins, files: vec![], project: None };
+        let rows = origin_rows(&layered);
+        let row = |key: &str, value: &str, origin: &str| (key.to_string(), value.to_string(), origin.to_string());
+        assert_eq!(
+            rows,
+            vec![
+                row("ots.kroki.image", "yuzutech/kroki", "global"),
+                row("ots.kroki.ports", "[\"8000:8000\"]", "project"),
+                row("profiles.docs", "[\"kroki\"]", "project"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,388 @@
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::env;
+use std::io;
+use std::path::{Path, PathBuf};
+use crate::config::{find_config_file, ConfigError, DkConfig, CONFIG_DIRECTORY, CONFIG_VERSION};
+use crate::dkutil::*;
+
+/// Fields of a service that `DK_OTS_<NAME>_<FIELD>` can override, lists are comma separated
+const ENV_FIELDS: &[(&str, &str, bool)] = &[
+    ("CONTAINER_NAME", "container_name", false),
+    ("IMAGE", "image", false),
+    ("RESTART", "restart", false),
+    ("PORTS", "ports", true),
+];
+
+/// A source of the configuration: a file or an environment variable
+#[derive(Debug, Clone, PartialEq)]
+pub struct Layer {
+    pub origin: String,
+    pub value: Value,
+}
+
+impl Layer {
+    /// Read a configuration file, it must be a valid configuration on its own
+    pub fn read(path: &Path) -> Result<Layer, ConfigError> {
+        let config = DkConfig::read_file(path)?;
+        if config.version < CONFIG_VERSION {
+            print_warning(&format!(
+                "{} is a version {} configuration, read as version {} (dk config migrate upgrades it)",
+                path.display(),
+                config.version,
+                CONFIG_VERSION
+            ));
+        }
+        Ok(Layer::from_config(&path.display().to_string(), &config))
+    }
+
+    pub fn from_config(origin: &str, config: &DkConfig) -> Layer {
+        Layer { origin: origin.to_string(), value: serde_json::to_value(config).unwrap_or_default() }
+    }
+}
+
+/// The configuration merged from its layers
+#[derive(Debug, Default)]
+pub struct LayeredConfig {
+    pub config: DkConfig,
+    /// Origin of each value, by key (`ots.<name>.<field>`, `profiles.<name>`, `aliases.<name>`)
+    pub origins: BTreeMap<String, String>,
+    /// Files read, the global one first
+    pub files: Vec<PathBuf>,
+    /// Project file, found from the current directory
+    pub project: Option<PathBuf>,
+}
+
+impl LayeredConfig {
+    /// Names of the services with a field set by the project file
+    pub fn project_services(&self) -> Vec<String> {
+        let Some(project) = self.project.as_ref().map(|p| p.display().to_string()) else {
+            return Vec::new();
+        };
+        let mut names: Vec<String> = self
+            .origins
+            .iter()
+            .filter(|(_, origin)| **origin == project)
+            .filter_map(|(key, _)| key.strip_prefix("ots.")?.rsplit_once('.').map(|(name, _)| name.to_string()))
+            .collect();
+        names.dedup();
+        names
+    }
+}
+
+/// Load the global file, the project file and the `DK_*` variables.
+///
+/// A missing global file is created with the defaults. An invalid file is an
+/// error: the commands must not act on services the user did not configure.
+pub fn load_config() -> Result<LayeredConfig, String> {
+    let vars: Vec<(String, String)> = env::vars().collect();
+    load(DkConfig::get_config_path(), &env::current_dir().unwrap_or_default(), &vars)
+}
+
+/// The project file of the current directory, without reading it
+pub fn project_file() -> Option<PathBuf> {
+    let global = DkConfig::get_config_path().ok();
+    find_project_file(&env::current_dir().unwrap_or_default(), global.as_deref(), home_config_dir().as_deref())
+}
+
+fn home_config_dir() -> Option<PathBuf> {
+    dirs::home_dir().map(|h| h.join(CONFIG_DIRECTORY))
+}
+
+/// Layers of the global file `global`, of the project file found from `current_dir` and of `vars`
+fn load(global: io::Result<PathBuf>, current_dir: &Path, vars: &[(String, String)]) -> Result<LayeredConfig, String> {
+    let mut layers = Vec::new();
+    let mut files = Vec::new();
+    let mut project = None;
+    match &global {
+        Ok(path) => match Layer::read(path) {
+            Ok(layer) => {
+                layers.push(layer);
+                files.push(path.clone());
+            }
+            Err(e) if e.is_missing() => {
+                layers.push(Layer::from_config(&path.display().to_string(), &DkConfig::create_default()));
+                if path.exists() {
+                    files.push(path.clone());
+                }
+            }
+            Err(e) => return Err(format!("Invalid configuration {} (fix it with dk ots edit)", e)),
+        },
+        Err(_) => layers.push(Layer::from_config("defaults", &DkConfig::create_default())),
+    }
+
+    if let Some(path) = find_project_file(current_dir, global.as_deref().ok(), home_config_dir().as_deref()) {
+        match Layer::read(&path) {
+            Ok(layer) => {
+                layers.push(layer);
+                files.push(path.clone());
+                project = Some(path);
+            }
+            Err(e) => return Err(format!("Invalid configuration {}", e)),
+        }
+    }
+
+    let (env_layers, warnings) = env_layers(vars, &merge(&layers).0);
+    for warning in &warnings {
+        print_warning(warning);
+    }
+    layers.extend(env_layers);
+
+    let (value, origins) = merge(&layers);
+    let config = serde_json::from_value(value).map_err(|e| format!("Invalid configuration : {}", e))?;
+    Ok(LayeredConfig { config, origins, files, project })
+}
+
+/// The project configuration: the first `.dk/dk_config.json` (or `.toml`,
+/// `.yaml`) found from `dir` up to the root, the global file and the `home`
+/// configuration directory excepted
+pub fn find_project_file(dir: &Path, global: Option<&Path>, home: Option<&Path>) -> Option<PathBuf> {
+    // ~/.dk n'est jamais un projet, même quand DK_CONFIG désigne un autre fichier
+    dir.ancestors()
+        .map(|d| d.join(CONFIG_DIRECTORY))
+        .filter(|d| home.is_none_or(|h| d != h))
+        .filter_map(|d| find_config_file(&d))
+        .find(|f| global.is_none_or(|g| f != g))
+}
+
+/// Merge the layers, the last one wins.
+///
+/// Services are merged field by field by name, profiles and aliases by name,
+/// other keys are replaced.
+pub fn merge(layers: &[Layer]) -> (Value, BTreeMap<String, String>) {
+    let mut services: Vec<Map<String, Value>> = Vec::new();
+    let mut merged = Map::new();
+    let mut origins = BTreeMap::new();
+    for layer in layers {
+        let Some(object) = layer.value.as_object() else {
+            continue;
+        };
+        for (key, value) in object {
+            match (key.as_str(), value) {
+                ("ots", Value::Array(entries)) => {
+                    for entry in entries.iter().filter_map(|e| e.as_object()) {
+                        let name = entry.get("name").and_then(|n| n.as_str()).unwrap_or_default();
+                        let index = match services.iter().position(|s| s.get("name").and_then(|n| n.as_str()) == Some(name)) {
+                            Some(index) => index,
+                            None => {
+                                services.push(Map::new());
+                                services.len() - 1
+                            }
+                        };
+                        for (field, value) in entry {
+                            services[index].insert(field.clone(), value.clone());
+                            if field != "name" {
+                                origins.insert(format!("ots.{}.{}", name, field), layer.origin.clone());
+                            }
+                        }
+                    }
+                }
+                ("profiles" | "aliases", Value::Object(items)) => {
+                    let target = merged
+                        .entry(key.clone())
+                        .or_insert_with(|| Value::Object(Map::new()));
+                    for (name, item) in items {
+                        if let Some(target) = target.as_object_mut() {
+                            target.insert(name.clone(), item.clone());
+                        }
+                        origins.insert(format!("{}.{}", key, name), layer.origin.clone());
+                    }
+                }
+                _ => {
+                    merged.insert(key.clone(), value.clone());
+                    origins.insert(key.clone(), layer.origin.clone());
+                }
+            }
+        }
+    }
+    merged.insert(String::from("ots"), Value::Array(services.into_iter().map(Value::Object).collect()));
+    (Value::Object(merged), origins)
+}
+
+/// Name of a service or a profile in a variable name (`my-web` gives `MY_WEB`)
+fn env_name(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_uppercase() } else { '_' })
+        .collect()
+}
+
+/// One layer per `DK_OTS_<NAME>_<FIELD>` and `DK_PROFILE_<NAME>` variable,
+/// the names are those of `merged`. Returns the layers and the ignored variables.
+pub fn env_layers(vars: &[(String, String)], merged: &Value) -> (Vec<Layer>, Vec<String>) {
+    let names: Vec<&str> = merged["ots"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|o| o["name"].as_str())
+        .collect();
+    let list = |value: &str| -> Value {
+        Value::Array(
+            value
+                .split(',')
+                .map(str::trim)
+                .filter(|v| !v.is_empty())
+                .map(|v| Value::String(v.to_string()))
+                .collect(),
+        )
+    };
+
+    let mut layers = Vec::new();
+    let mut warnings = Vec::new();
+    let mut sorted: Vec<&(String, String)> = vars.iter().collect();
+    sorted.sort();
+    for (var, value) in sorted {
+        let origin = format!("${}", var);
+        if let Some(rest) = var.strip_prefix("DK_OTS_") {
+            let field = ENV_FIELDS.iter().find_map(|(suffix, field, is_list)| {
+                let name = rest.strip_suffix(suffix)?.strip_suffix('_')?;
+                Some((name, *field, *is_list))
+            });
+            let Some((name, field, is_list)) = field else {
+                warnings.push(format!("{} ignored: unknown field", var));
+                continue;
+            };
+            let Some(name) = names.iter().find(|n| env_name(n) == name) else {
+                warnings.push(format!("{} ignored: no OTS named {}", var, name.to_lowercase()));
+                continue;
+            };
+            let value = if is_list { list(value) } else { Value::String(value.clone()) };
+            let mut entry = Map::new();
+            entry.insert(String::from("name"), Value::String(name.to_string()));
+            entry.insert(field.to_string(), value);
+            let mut object = Map::new();
+            object.insert(String::from("ots"), Value::Array(vec![Value::Object(entry)]));
+            layers.push(Layer { origin, value: Value::Object(object) });
+        } else if let Some(name) = var.strip_prefix("DK_PROFILE_") {
+            let profile = merged["profiles"]
+                .as_object()
+                .and_then(|p| p.keys().find(|p| env_name(p) == name).cloned())
+                .unwrap_or_else(|| name.to_lowercase());
+            let mut profiles = Map::new();
+            profiles.insert(profile, list(value));
+            let mut object = Map::new();
+            object.insert(String::from("profiles"), Value::Object(profiles));
+            layers.push(Layer { origin, value: Value::Object(object) });
+        }
+    }
+    (layers, warnings)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::fs;
+
+    fn layers() -> Vec<Layer> {
+        vec![
+            Layer {
+                origin: String::from("global"),
+                value: json!({
+                    "ots": [
+                        { "name": "kroki", "image": "yuzutech/kroki", "ports": ["25100:8000"] },
+                        { "name": "my-web", "image": "nginx" }
+                    ],
+                    "profiles": { "docs": ["kroki"] },
+                    "aliases": { "clean": "sys prune", "docs": "ots up @docs" }
+                }),
+            },
+            Layer {
+                origin: String::from("project"),
+                value: json!({
+                    "ots": [
+                        { "name": "kroki", "ports": ["8000:8000"] },
+                        { "name": "db", "image": "postgres:17" }
+                    ],
+                    "profiles": { "dev": ["db", "kroki"] },
+                    "aliases": { "dev": "ots up @dev" }
+                }),
+            },
+        ]
+    }
+
+    #[test]
+    fn test_merge() {
+        let (value, origins) = merge(&layers());
+        let config: DkConfig = serde_json::from_value(value).unwrap();
+        let names: Vec<&str> = config.ots.iter().map(|o| o.name.as_str()).collect();
+        assert_eq!(names, vec!["kroki", "my-web", "db"]);
+        assert_eq!(config.ots[0].image, "yuzutech/kroki");
+        assert_eq!(config.ots[0].ports, vec!["8000:8000"]);
+        assert_eq!(config.profiles.len(), 2);
+        assert_eq!(origins["ots.kroki.image"], "global");
+        assert_eq!(origins["ots.kroki.ports"], "project");
+        assert_eq!(origins["profiles.dev"], "project");
+        assert_eq!(config.aliases.len(), 3);
+        assert_eq!(origins["aliases.docs"], "global");
+        assert_eq!(origins["aliases.dev"], "project");
+        assert!(!origins.contains_key("ots.kroki.name"));
+    }
+
+    #[test]
+    fn test_env_layers() {
+        let mut layers = layers();
+        let vars: Vec<(String, String)> = [
+            ("DK_OTS_MY_WEB_IMAGE", "nginx:1.27"),
+            ("DK_OTS_KROKI_PORTS", "9000:8000, 9001:8001"),
+            ("DK_OTS_CACHE_IMAGE", "redis"),
+            ("DK_OTS_KROKI_NETWORK", "host"),
+            ("DK_PROFILE_DOCS", "kroki,my-web"),
+            ("DK_BACKEND", "api"),
+        ]
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+        let (env, warnings) = env_layers(&vars, &merge(&layers).0);
+        assert_eq!(env.len(), 3);
+        assert_eq!(warnings, vec!["DK_OTS_CACHE_IMAGE ignored: no OTS named cache", "DK_OTS_KROKI_NETWORK ignored: unknown field"]);
+        layers.extend(env);
+
+        let (value, origins) = merge(&layers);
+        let config: DkConfig = serde_json::from_value(value).unwrap();
+        assert_eq!(config.ots[1].image, "nginx:1.27");
+        assert_eq!(config.ots[0].ports, vec!["9000:8000", "9001:8001"]);
+        assert_eq!(config.profiles["docs"], vec!["kroki", "my-web"]);
+        assert_eq!(origins["ots.my-web.image"], "$DK_OTS_MY_WEB_IMAGE");
+    }
+
+    #[test]
+    fn test_find_project_file() {
+        let root = env::temp_dir().join(format!("dk_layers_{}", std::process::id()));
+        let nested = root.join("src").join("module");
+        fs::create_dir_all(&nested).unwrap();
+        fs::create_dir_all(root.join(CONFIG_DIRECTORY)).unwrap();
+        let project = root.join(CONFIG_DIRECTORY).join("dk_config.yaml");
+        fs::write(&project, "version: 2\nots: []\n").unwrap();
+
+        assert_eq!(find_project_file(&nested, None, None), Some(project.clone()));
+        assert_eq!(find_project_file(&nested, Some(&project), None), None);
+        assert_eq!(find_project_file(&nested, None, Some(&root.join(CONFIG_DIRECTORY))), None);
+        assert_eq!(Layer::read(&project).unwrap().origin, project.display().to_string());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_load_invalid_file() {
+        let root = env::temp_dir().join(format!("dk_layers_invalid_{}", std::process::id()));
+        let project = root.join("work").join(CONFIG_DIRECTORY).join("dk_config.yaml");
+        fs::create_dir_all(project.parent().unwrap()).unwrap();
+        let global = root.join("dk_config.json");
+        fs::write(&global, r#"{"version": 2, "ots": []}"#).unwrap();
+        fs::write(&project, "version: 2\nots:\n  - name: kroki\n    ports: [\"8000:8000\"]\n").unwrap();
+        let vars = [(String::from("DK_OTS_KROKI_IMAGE"), String::from("kroki"))];
+
+        let layered = load(Ok(global.clone()), &root.join("work"), &vars).unwrap();
+        assert_eq!(layered.files, vec![global.clone(), project.clone()]);
+        assert_eq!(layered.project, Some(project.clone()));
+        assert_eq!(layered.project_services(), vec!["kroki"]);
+
+        // aucune valeur par défaut ne remplace un fichier invalide
+        fs::write(&project, "version: 2\nots: [\n").unwrap();
+        assert!(load(Ok(global.clone()), &root.join("work"), &vars).is_err());
+        fs::write(&global, r#"{"version": 2, "ots": "#).unwrap();
+        let error = load(Ok(global.clone()), &root, &vars).unwrap_err();
+        assert!(error.ends_with("(fix it with dk ots edit)"), "{}", error);
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
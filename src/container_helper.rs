@@ -1,98 +1,64 @@
 
-use prettytable::{Attr, Cell, Row, Table, color, format};
-use colored::*;
-use std::process::Command;
+use prettytable::{Attr, Cell, Row};
+use std::fs::OpenOptions;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use crate::cli;
+use crate::command_executor::*;
 use crate::dkutil::*;
+use crate::models::*;
+use crate::output::*;
 
 pub fn usage() {
-    println!("{}", "CONTAINERS:".cyan());
+    cli::print_usage_section("CONTAINERS");
+}
+
+/// The containers, running or not; an error when docker cannot list them
+pub fn get_containers(executor: &dyn CommandExecutor) -> Result<Vec<Container>, String> {
+    if let Some(engine) = executor.engine() {
+        return engine.containers().map(|containers| containers.into_iter().map(Container::from).collect());
+    }
 
-    print_colored("(y) . dk ps              (w): Show state of the containers");
-    print_colored("(y) . dk rm (b)<container*> (w): Remove container(s)");
-    print_colored("(y) . dk shell           (w): Run a bash shell into the container");
+    let stdout = capture(executor, &["docker", "ps", "-a", "--format", "{{json .}}"])?;
+    parse_json_lines(&stdout)
 }
 
-pub fn get_containers() -> Vec<Vec<String>> {
-    let output = Command::new("docker")
-        .args(&[
-            "ps",
-            "-a",
-            "--format",
-            "{{.Names}}|{{.ID}}|{{.Image}}|{{.Status}}",
-        ])
-        .output()
-        .expect("Échec de 'docker ps'");
-    let stdout = String::from_utf8_lossy(&output.stdout);
-    let mut table = Vec::new();
-    let mut index = 1;
-    for line in stdout.lines() {
-        let parts: Vec<&str> = line.split('|').collect();
-        if parts.len() < 4 {
-            continue;
+pub fn show(executor: &dyn CommandExecutor, format: OutputFormat) -> i32 {
+    let containers = match get_containers(executor) {
+        Ok(containers) => containers,
+        Err(e) => {
+            eprint_error(&e);
+            return 1;
         }
-        table.push(vec![
-            index.to_string(),
-            parts[0].to_string(),
-            parts[1].to_string(),
-            parts[2].to_string(),
-            parts[3].to_string(),
-        ]);
-        index += 1;
+    };
+    if let Some(out) = render(&containers, format) {
+        print!("{}", out);
+        return 0;
     }
-    table
-}
+    let mut table = new_table(&["Index", "ID", "Name", "Image", "Status"]);
 
-pub fn show()  {
-    let containers = get_containers();
-
-    let mut table = Table::new();
-
-    let format = format::FormatBuilder::new()
-        .column_separator(' ') // séparateur de colonne
-        .borders(' ') // bordures
-        .padding(1, 1)
-        .build();
-    table.set_format(format);
-
-    table.set_titles(Row::new(vec![
-        Cell::new("Index")
-            .with_style(Attr::Bold)
-            .with_style(Attr::ForegroundColor(color::GREEN)),
-        Cell::new("ID")
-            .with_style(Attr::Bold)
-            .with_style(Attr::ForegroundColor(color::GREEN)),
-        Cell::new("Name")
-            .with_style(Attr::Bold)
-            .with_style(Attr::ForegroundColor(color::GREEN)),
-        Cell::new("Image")
-            .with_style(Attr::Bold)
-            .with_style(Attr::ForegroundColor(color::GREEN)),
-        Cell::new("Status")
-            .with_style(Attr::Bold)
-            .with_style(Attr::ForegroundColor(color::GREEN))
-    ]));
-
-    for r in containers {
+    for (index, c) in containers.iter().enumerate() {
         table.add_row(Row::new(vec![
-            Cell::new(&r[0]),
-            Cell::new(&r[2]),
-            Cell::new(&r[1]).with_style(Attr::Bold),
-            Cell::new(&r[3]),
-            Cell::new(&r[4])
+            Cell::new(&(index + 1).to_string()),
+            Cell::new(&c.id),
+            Cell::new(&c.name).with_style(Attr::Bold),
+            Cell::new(&c.image),
+            Cell::new(&c.status)
         ]));
     }
 
-    table.printstd();
+    print_table(&table);
+    0
 }
 
-pub fn remove(filters: &[String]) -> i32 {
+pub fn remove(executor: &dyn CommandExecutor, filters: &[String]) -> i32 {
     let mut retcode=0;
-    let ids = translate_to_id(filters);
+    let ids = translate_to_id(executor, filters);
 
 
     for f  in ids {
         print_info(&format!("Removing container {}", f));
-        let i= print_and_run(&["docker", "rm", "-f", &f]);
+        let i= print_and_run(executor, &["docker", "rm", "-f", &f]);
     
         if i != 0 {
             retcode = i;
@@ -103,22 +69,162 @@ pub fn remove(filters: &[String]) -> i32 {
 }
 
 
-pub fn exec_shell(container: &str) -> i32 {
+pub fn exec_shell(executor: &dyn CommandExecutor, container: &str) -> i32 {
     print_info(&format!("Executing shell in container {}", container));
-    return print_and_run(&["docker", "exec", "-it", container, "/bin/bash"]);
+    print_and_run(executor, &["docker", "exec", "-it", container, "/bin/bash"])
 }
 
 
-fn translate_to_id(filters: &[String]) -> Vec<String> {
-    let containers = get_containers();
-    let max_rank = containers.iter().count();
-    
-    filters.iter().map(|f| {
-        if is_valid_rank(f, max_rank) {
-            let row = &containers[f.parse::<usize>().unwrap() - 1];
-            row[1].clone()
-        } else {
-            f.to_string()
+fn translate_to_id(executor: &dyn CommandExecutor, filters: &[String]) -> Vec<String> {
+    // sans la liste, les filtres sont pris comme des noms
+    let containers = get_containers(executor).unwrap_or_else(|e| {
+        print_error(&e);
+        Vec::new()
+    });
+    translate_ranks(&containers, filters, |c| c.name.clone())
+}
+
+
+/// Truncate the log file of a set of containers
+///
+/// # Arguments
+///
+/// * `filters` - Container ranks or names
+/// * `keep` - Number of bytes to keep at the end of each log (0 to empty it)
+///
+pub fn trunclog(executor: &dyn CommandExecutor, filters: &[String], keep: u64) -> i32 {
+    let mut retcode = 0;
+    for id in translate_to_id(executor, filters) {
+        let output = executor.execute(&[
+            "docker",
+            "inspect",
+            "--format",
+            "{{.Name}}|{{.HostConfig.LogConfig.Type}}|{{.LogPath}}",
+            &id,
+        ]);
+        let stdout = match output {
+            Ok(o) => o.trim().to_string(),
+            Err(_) => {
+                print_error(&format!("Container {} not found", id));
+                retcode = 1;
+                continue;
+            }
+        };
+        let parts: Vec<&str> = stdout.splitn(3, '|').collect();
+        if parts.len() < 3 {
+            print_error(&format!("Unable to inspect container {}", id));
+            retcode = 1;
+            continue;
+        }
+        let name = parts[0].trim_start_matches('/');
+        let driver = parts[1];
+        let log_path = parts[2];
+
+        if driver != "json-file" {
+            // le pilote local n'expose pas de LogPath : ses fichiers compressés sont gérés par docker
+            let message = if driver == "local" {
+                format!("Container {} uses the 'local' logging driver, whose files are managed by docker (only json-file is supported)", name)
+            } else {
+                format!("Container {} uses the '{}' logging driver, only json-file is supported", name, driver)
+            };
+            print_error(&message);
+            retcode = 1;
+            continue;
+        }
+        if log_path.is_empty() {
+            print_error(&format!("Container {} has no log file", name));
+            retcode = 1;
+            continue;
+        }
+
+        print_info(&format!("Truncating log of container {} ({})", name, log_path));
+        match truncate_log(Path::new(log_path), keep) {
+            Ok(reclaimed) => println!("{}: {} reclaimed", name, format_size(reclaimed)),
+            Err(e) => {
+                print_error(&format!("Unable to truncate {} : {}", log_path, e));
+                if e.kind() == io::ErrorKind::PermissionDenied {
+                    print_error("The docker log files are usually owned by root, try with sudo");
+                }
+                retcode = 1;
+            }
         }
-    }).collect()
-}
\ No newline at end of file
+    }
+    retcode
+}
+
+/// Truncate a log file, keeping at most `keep` bytes at its end.
+///
+/// The kept part starts on a line boundary so that every remaining line is
+/// still a complete JSON record. Returns the number of bytes reclaimed.
+fn truncate_log(path: &Path, keep: u64) -> io::Result<u64> {
+    let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+    let len = file.metadata()?.len();
+    if keep == 0 {
+        file.set_len(0)?;
+        return Ok(len);
+    }
+    if len <= keep {
+        return Ok(0);
+    }
+
+    file.seek(SeekFrom::Start(len - keep))?;
+    let mut tail = Vec::new();
+    file.read_to_end(&mut tail)?;
+    let start = match tail.iter().position(|b| *b == b'\n') {
+        Some(pos) => pos + 1,
+        None => tail.len(),
+    };
+    let tail = &tail[start..];
+
+    file.seek(SeekFrom::Start(0))?;
+    file.write_all(tail)?;
+    file.set_len(tail.len() as u64)?;
+    Ok(len - tail.len() as u64)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_truncate_log() {
+        let path = std::env::temp_dir().join(format!("dk_trunclog_{}.log", std::process::id()));
+
+        fs::write(&path, "{\"log\":\"one\"}\n{\"log\":\"two\"}\n{\"log\":\"three\"}\n").unwrap();
+        // keeps only complete lines from the tail
+        let reclaimed = truncate_log(&path, 20).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "{\"log\":\"three\"}\n");
+        assert_eq!(reclaimed, 28);
+
+        // nothing to do when the file is already small enough
+        assert_eq!(truncate_log(&path, 1024).unwrap(), 0);
+
+        assert_eq!(truncate_log(&path, 0).unwrap(), 16);
+        assert_eq!(fs::metadata(&path).unwrap().len(), 0);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_trunclog_inspects_the_container() {
+        let mut mock = MockCommandExecutor::new();
+        mock.expect(&["docker", "ps", "..."], MockResponse::fixture("docker_ps.txt"))
+            .expect(&["docker", "inspect", "--format", "*", "ots_kroki"], MockResponse::fixture("docker_inspect_log.txt"))
+            .expect(&["docker", "inspect", "--format", "*", "ots_sqlserver"], MockResponse::ok("/ots_sqlserver|journald|\n"));
+
+        // the log file of the fixture does not exist here, the other container uses journald
+        let args = vec![String::from("2"), String::from("ots_sqlserver")];
+        assert_eq!(trunclog(&mock, &args, 0), 1);
+        assert_eq!(
+            mock.command_lines()[1],
+            "docker inspect --format {{.Name}}|{{.HostConfig.LogConfig.Type}}|{{.LogPath}} ots_kroki"
+        );
+        assert_eq!(mock.calls().len(), 3);
+
+        let mut mock = MockCommandExecutor::new();
+        mock.expect(&["docker", "ps", "..."], MockResponse::fixture("docker_ps.txt"))
+            .expect(&["docker", "inspect", "..."], MockResponse::ok("/ots_kroki|local|\n"));
+        assert_eq!(trunclog(&mock, &[String::from("ots_kroki")], 0), 1);
+    }
+}
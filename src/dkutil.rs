@@ -1,32 +1,99 @@
 use colored::*;
+use prettytable::{Attr, Cell, Row, Table, color, format};
 use regex::Regex;
-use std::process::Command;
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use crate::command_executor::*;
+
+static COLOR: AtomicBool = AtomicBool::new(true);
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+/// Enable or disable the colors of every output (`--no-color`, `NO_COLOR`)
+pub fn set_color(enabled: bool) {
+    COLOR.store(enabled, Ordering::Relaxed);
+    colored::control::set_override(enabled);
+}
+
+pub fn color_enabled() -> bool {
+    COLOR.load(Ordering::Relaxed)
+}
+
+/// Silence the info and error messages (the output of `dk __complete` is read by the shell)
+pub fn set_quiet(quiet: bool) {
+    QUIET.store(quiet, Ordering::Relaxed);
+}
 
 pub fn print_info(info: &str) {
-    println!("-- {}", info.green());
+    if !QUIET.load(Ordering::Relaxed) {
+        println!("-- {}", info.green());
+    }
 }
 
 pub fn print_error(info: &str) {
-    println!("-- {}", info.red());
+    if !QUIET.load(Ordering::Relaxed) {
+        println!("-- {}", info.red());
+    }
+}
+
+/// Print an error on the error output, the standard output being a result (e.g. `dk ps -o json`)
+pub fn eprint_error(info: &str) {
+    if !QUIET.load(Ordering::Relaxed) {
+        eprintln!("-- {}", info.red());
+    }
+}
+
+/// Print a warning on the error output, the standard output being a result (e.g. `dk ots export`)
+pub fn print_warning(info: &str) {
+    if !QUIET.load(Ordering::Relaxed) {
+        eprintln!("-- {}", info.yellow());
+    }
+}
+
+/// Ask a yes/no question on the terminal, `default` being the answer to an
+/// empty line (the end of the input is a no)
+pub fn confirm(question: &str, default: bool) -> bool {
+    let choices = if default { "[Y/n]" } else { "[y/N]" };
+    print!("-- {} {} ", question.yellow(), choices);
+    let _ = io::Write::flush(&mut io::stdout());
+    let mut answer = String::new();
+    if !matches!(io::stdin().read_line(&mut answer), Ok(n) if n > 0) {
+        return false;
+    }
+    match answer.trim().to_lowercase().as_str() {
+        "" => default,
+        answer => answer.starts_with('y') || answer.starts_with('o'),
+    }
 }
 
 /// Execute a system command and dhow the executed command
-pub fn print_and_run(cmd: &[&str]) -> i32{
+pub fn print_and_run(executor: &dyn CommandExecutor, cmd: &[&str]) -> i32 {
     let cmdstr = cmd.join(" ");
     print_info(&cmdstr);
-    let status = Command::new(cmd[0])
-        .args(&cmd[1..])
-        .status()
-        .expect("Failed to execute the command");
-    
-    let code = status.code().unwrap_or(0); 
-    
-    if !status.success() {
+    let code = match executor.run(cmd) {
+        Ok(code) => code,
+        Err(e) => {
+            print_error(&e);
+            return 127;
+        }
+    };
+
+    if code != 0 {
         let msg = format!("Exit code : {}",code);
         print_error(&msg);
     }
-    return code;
+    code
+}
+
+/// Execute a command and return its output, or its error message
+pub fn capture(executor: &dyn CommandExecutor, cmd: &[&str]) -> Result<String, String> {
+    executor.execute(cmd).map_err(|e| e.trim().to_string())
+}
 
+/// Short form (12 characters) of a docker id, without the `sha256:` prefix
+pub fn short_id(id: &str) -> String {
+    let id = id.strip_prefix("sha256:").unwrap_or(id);
+    id.chars().take(12).collect()
 }
 
 pub fn is_integer(s: &str) -> bool {
@@ -41,11 +108,204 @@ pub fn is_valid_rank(v: &str, max: usize) -> bool {
             Ok(rank) => rank > 0 && rank <= max,
             Err(_) => false // Retourne false si le parsing échoue (par exemple pour les nombres négatifs)
         }
-    }  else
-    {
+    } else {
         false
     }
-    
+}
+
+/// Parse a human readable size such as `512`, `64k`, `10M` or `1GB`.
+/// Units are binary (1k = 1024 bytes).
+pub fn parse_size(s: &str) -> Option<u64> {
+    let re = Regex::new(r"^(\d+)\s*([kKmMgG]?)(i?[bB])?$").unwrap();
+    let caps = re.captures(s.trim())?;
+    let value = caps[1].parse::<u64>().ok()?;
+    let factor: u64 = match caps[2].to_ascii_lowercase().as_str() {
+        "k" => 1024,
+        "m" => 1024 * 1024,
+        "g" => 1024 * 1024 * 1024,
+        _ => 1,
+    };
+    value.checked_mul(factor)
+}
+
+/// Parse a duration such as `90`, `60s`, `500ms`, `2m` or `1h` (seconds by default)
+pub fn parse_duration(s: &str) -> Option<Duration> {
+    let re = Regex::new(r"^(\d+)\s*(ms|s|m|h)?$").unwrap();
+    let caps = re.captures(s.trim())?;
+    let value = caps[1].parse::<u64>().ok()?;
+    match caps.get(2).map_or("s", |u| u.as_str()) {
+        "ms" => Some(Duration::from_millis(value)),
+        "m" => value.checked_mul(60).map(Duration::from_secs),
+        "h" => value.checked_mul(3600).map(Duration::from_secs),
+        _ => Some(Duration::from_secs(value)),
+    }
+}
+
+/// Format a size in bytes as a short human readable string (e.g. `1.5M`).
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["K", "M", "G", "T"];
+    if bytes < 1024 {
+        return format!("{}B", bytes);
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1}{}", value, UNITS[unit])
+}
+
+/// Format a size the way the docker CLI does (`301MB`, `1.61GB`, `44.2MB`)
+pub fn format_docker_size(bytes: u64) -> String {
+    const UNITS: [&str; 6] = ["B", "kB", "MB", "GB", "TB", "PB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1000.0 && unit < UNITS.len() - 1 {
+        value /= 1000.0;
+        unit += 1;
+    }
+    let digits = if value >= 100.0 { 0 } else if value >= 10.0 { 1 } else { 2 };
+    let mut number = format!("{:.*}", digits, value);
+    if number.contains('.') {
+        number = number.trim_end_matches('0').trim_end_matches('.').to_string();
+    }
+    format!("{}{}", number, UNITS[unit])
+}
+
+/// Format a unix timestamp as `2025-04-14 08:12:33 +0000 UTC`
+pub fn format_timestamp(secs: i64) -> String {
+    let days = secs.div_euclid(86400);
+    let rem = secs.rem_euclid(86400);
+    // civil from days (H. Hinnant)
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02} +0000 UTC",
+        year, month, day, rem / 3600, rem % 3600 / 60, rem % 60
+    )
+}
+
+/// Split a command line into words the way a POSIX shell does.
+///
+/// Handles single and double quotes, backslash escapes and `\` line
+/// continuations. No expansion is done.
+pub fn split_shell_words(line: &str) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => {
+                in_word = true;
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => word.push(c),
+                        None => return Err(format!("Unterminated quote in: {}", line)),
+                    }
+                }
+            }
+            '"' => {
+                in_word = true;
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some('\n') => {}
+                            Some(c @ ('"' | '\\' | '$' | '`')) => word.push(c),
+                            Some(c) => {
+                                word.push('\\');
+                                word.push(c);
+                            }
+                            None => return Err(format!("Unterminated quote in: {}", line)),
+                        },
+                        Some(c) => word.push(c),
+                        None => return Err(format!("Unterminated quote in: {}", line)),
+                    }
+                }
+            }
+            '\\' => match chars.next() {
+                // continuation de ligne
+                Some('\n') => {}
+                Some(c) => {
+                    in_word = true;
+                    word.push(c);
+                }
+                None => return Err(format!("Trailing backslash in: {}", line)),
+            },
+            c if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            c => {
+                in_word = true;
+                word.push(c);
+            }
+        }
+    }
+    if in_word {
+        words.push(word);
+    }
+    Ok(words)
+}
+
+/// Translate filters into identifiers.
+///
+/// A filter that is a valid rank (1 based index in `items`) is replaced by the
+/// identifier of the item, any other filter is kept as-is.
+pub fn translate_ranks<T>(items: &[T], filters: &[String], id: impl Fn(&T) -> String) -> Vec<String> {
+    filters
+        .iter()
+        .map(|f| {
+            if is_valid_rank(f, items.len()) {
+                id(&items[f.parse::<usize>().unwrap() - 1])
+            } else {
+                f.to_string()
+            }
+        })
+        .collect()
+}
+
+/// Build a table with the format and the green titles used by the listings
+pub fn new_table(titles: &[&str]) -> Table {
+    let mut table = Table::new();
+    let format = format::FormatBuilder::new()
+        .column_separator(' ') // séparateur de colonne
+        .borders(' ') // bordures
+        .padding(1, 1)
+        .build();
+    table.set_format(format);
+    table.set_titles(Row::new(
+        titles
+            .iter()
+            .map(|t| {
+                Cell::new(t)
+                    .with_style(Attr::Bold)
+                    .with_style(Attr::ForegroundColor(color::GREEN))
+            })
+            .collect(),
+    ));
+    table
+}
+
+/// Print a table, without any terminal style when the colors are disabled
+pub fn print_table(table: &Table) {
+    if color_enabled() {
+        table.printstd();
+    } else {
+        let _ = table.print(&mut io::stdout());
+    }
 }
 
 pub fn print_colored(text: &str) {
@@ -130,6 +390,72 @@ mod tests {
         // assert!(!is_valid_rank("abc", 5));
     }
 
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("512"), Some(512));
+        assert_eq!(parse_size("64k"), Some(64 * 1024));
+        assert_eq!(parse_size("10M"), Some(10 * 1024 * 1024));
+        assert_eq!(parse_size("1GB"), Some(1024 * 1024 * 1024));
+        assert_eq!(parse_size("2MiB"), Some(2 * 1024 * 1024));
+        assert_eq!(parse_size("1.5M"), None);
+        assert_eq!(parse_size("abc"), None);
+        assert_eq!(parse_size(""), None);
+    }
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!(format_size(0), "0B");
+        assert_eq!(format_size(1023), "1023B");
+        assert_eq!(format_size(1536), "1.5K");
+        assert_eq!(format_size(10 * 1024 * 1024), "10.0M");
+    }
+
+    #[test]
+    fn test_format_docker_size() {
+        assert_eq!(format_docker_size(512), "512B");
+        assert_eq!(format_docker_size(301_000_000), "301MB");
+        assert_eq!(format_docker_size(1_610_000_000), "1.61GB");
+        assert_eq!(format_docker_size(44_200_000), "44.2MB");
+        assert_eq!(format_docker_size(2_000), "2kB");
+    }
+
+    #[test]
+    fn test_format_timestamp() {
+        assert_eq!(format_timestamp(0), "1970-01-01 00:00:00 +0000 UTC");
+        assert_eq!(format_timestamp(1744618353), "2025-04-14 08:12:33 +0000 UTC");
+        assert_eq!(format_timestamp(951782400), "2000-02-29 00:00:00 +0000 UTC");
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("90"), Some(Duration::from_secs(90)));
+        assert_eq!(parse_duration("60s"), Some(Duration::from_secs(60)));
+        assert_eq!(parse_duration("500ms"), Some(Duration::from_millis(500)));
+        assert_eq!(parse_duration("2m"), Some(Duration::from_secs(120)));
+        assert_eq!(parse_duration("1h"), Some(Duration::from_secs(3600)));
+        assert_eq!(parse_duration("1d"), None);
+        assert_eq!(parse_duration("-5s"), None);
+    }
+
+    #[test]
+    fn test_split_shell_words() {
+        let words = split_shell_words("docker run -e 'A=x y' -e B=\"a \\\"b\\\"\" \\\n  --label c\\ d img").unwrap();
+        assert_eq!(words, vec!["docker", "run", "-e", "A=x y", "-e", "B=a \"b\"", "--label", "c d", "img"]);
+        assert_eq!(split_shell_words("  a ''  b").unwrap(), vec!["a", "", "b"]);
+        assert!(split_shell_words("run 'oops").is_err());
+        assert!(split_shell_words("run \\").is_err());
+    }
+
+    #[test]
+    fn test_translate_ranks() {
+        let items = vec!["alpha", "beta", "gamma"];
+        let filters: Vec<String> = ["2", "delta", "4", "0"].iter().map(|s| s.to_string()).collect();
+        assert_eq!(
+            translate_ranks(&items, &filters, |i| i.to_uppercase()),
+            vec!["BETA", "delta", "4", "0"]
+        );
+    }
+
     #[test]
     fn test_print_colored() {
         // Test visuel : vérifie que la fonction ne panique pas
@@ -139,17 +465,24 @@ mod tests {
     #[test]
     fn test_print_and_run_success() {
         // Test avec une commande simple qui réussit
-        let exit_code = print_and_run(&["echo", "Hello, world!"]);
+        let exit_code = print_and_run(&RealCommandExecutor, &["echo", "Hello, world!"]);
         assert_eq!(exit_code, 0);
     }
 
     #[test]
     fn test_print_and_run_failure() {
         // Test avec une commande qui échoue
-        let exit_code = print_and_run(&["false"]);
+        let exit_code = print_and_run(&RealCommandExecutor, &["false"]);
         assert_ne!(exit_code, 0);
     }
 
+    #[test]
+    fn test_print_and_run_missing_command() {
+        // Pas de panique quand la commande n'existe pas
+        let exit_code = print_and_run(&RealCommandExecutor, &["dk-no-such-command"]);
+        assert_eq!(exit_code, 127);
+    }
+
     #[test]
     fn test_colorize() {
         // Test des couleurs
@@ -0,0 +1,358 @@
+// hors Unix le client existe pour les types partagés mais n'est jamais créé
+#![cfg_attr(not(unix), allow(dead_code))]
+
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::path::PathBuf;
+use crate::command_executor::*;
+
+const DEFAULT_SOCKET: &str = "/var/run/docker.sock";
+
+/// A container as returned by `GET /containers/json`
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "PascalCase")]
+pub struct ContainerSummary {
+    pub id: String,
+    #[serde(default)]
+    pub names: Vec<String>,
+    pub image: String,
+    #[serde(default)]
+    pub state: String,
+    #[serde(default)]
+    pub status: String,
+    #[serde(default)]
+    pub ports: Vec<PortSummary>,
+    #[serde(default)]
+    pub created: i64,
+}
+
+/// A port of a container as returned by `GET /containers/json`
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "PascalCase")]
+pub struct PortSummary {
+    #[serde(rename = "IP", default)]
+    pub ip: Option<String>,
+    pub private_port: u16,
+    #[serde(default)]
+    pub public_port: Option<u16>,
+    #[serde(rename = "Type", default)]
+    pub kind: String,
+}
+
+impl fmt::Display for PortSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.public_port {
+            Some(public) => write!(
+                f,
+                "{}:{}->{}/{}",
+                self.ip.as_deref().unwrap_or("0.0.0.0"),
+                public,
+                self.private_port,
+                self.kind
+            ),
+            None => write!(f, "{}/{}", self.private_port, self.kind),
+        }
+    }
+}
+
+/// An image as returned by `GET /images/json`
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "PascalCase")]
+pub struct ImageSummary {
+    pub id: String,
+    #[serde(default)]
+    pub repo_tags: Option<Vec<String>>,
+    #[serde(default)]
+    pub repo_digests: Option<Vec<String>>,
+    #[serde(default)]
+    pub created: i64,
+    #[serde(default)]
+    pub size: i64,
+}
+
+/// A volume as returned by `GET /volumes`
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "PascalCase")]
+pub struct VolumeSummary {
+    pub name: String,
+    #[serde(default)]
+    pub driver: String,
+    #[serde(default)]
+    pub mountpoint: String,
+    #[serde(default)]
+    pub created_at: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct VolumeList {
+    volumes: Option<Vec<VolumeSummary>>,
+}
+
+/// Minimal client of the Docker Engine REST API over its Unix socket
+pub struct EngineClient {
+    socket: PathBuf,
+}
+
+impl EngineClient {
+    pub fn new(socket: PathBuf) -> Self {
+        Self { socket }
+    }
+
+    /// Build the client from `DOCKER_HOST` (only `unix://` hosts are supported)
+    /// or use the default socket `/var/run/docker.sock`.
+    pub fn from_env() -> Result<Self, String> {
+        match env::var("DOCKER_HOST") {
+            Ok(host) if !host.is_empty() => match host.strip_prefix("unix://") {
+                Some(path) => Ok(Self::new(PathBuf::from(path))),
+                None => Err(format!("DOCKER_HOST '{}' is not a unix socket", host)),
+            },
+            _ => Ok(Self::new(PathBuf::from(DEFAULT_SOCKET))),
+        }
+    }
+
+    pub fn containers(&self) -> Result<Vec<ContainerSummary>, String> {
+        self.get_json("/containers/json?all=1")
+    }
+
+    pub fn images(&self) -> Result<Vec<ImageSummary>, String> {
+        self.get_json("/images/json")
+    }
+
+    pub fn volumes(&self) -> Result<Vec<VolumeSummary>, String> {
+        let list: VolumeList = self.get_json("/volumes")?;
+        Ok(list.volumes.unwrap_or_default())
+    }
+
+    fn get_json<T: DeserializeOwned>(&self, path: &str) -> Result<T, String> {
+        let body = self.get(path)?;
+        serde_json::from_slice(&body).map_err(|e| format!("Invalid answer of the docker engine for {}: {}", path, e))
+    }
+
+    /// Send a GET request and return the body of the answer
+    #[cfg(unix)]
+    fn get(&self, path: &str) -> Result<Vec<u8>, String> {
+        use std::io::{Read, Write};
+        use std::os::unix::net::UnixStream;
+        use std::time::Duration;
+        let socket_error = |e: std::io::Error| format!("Unable to talk to docker on {}: {}", self.socket.display(), e);
+
+        let mut stream = UnixStream::connect(&self.socket).map_err(socket_error)?;
+        stream.set_read_timeout(Some(Duration::from_secs(30))).map_err(socket_error)?;
+        let request = format!("GET {} HTTP/1.1\r\nHost: docker\r\nUser-Agent: dk\r\nConnection: close\r\n\r\n", path);
+        stream.write_all(request.as_bytes()).map_err(socket_error)?;
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).map_err(socket_error)?;
+
+        let HttpResponse { code, headers, body } = parse_response(&response)?;
+        let body = if headers.get("transfer-encoding").is_some_and(|v| v.eq_ignore_ascii_case("chunked")) {
+            dechunk(body)?
+        } else {
+            body.to_vec()
+        };
+
+        if !(200..300).contains(&code) {
+            let message = serde_json::from_slice::<serde_json::Value>(&body)
+                .ok()
+                .and_then(|v| v["message"].as_str().map(String::from))
+                .unwrap_or_else(|| String::from_utf8_lossy(&body).trim().to_string());
+            return Err(format!("Docker engine error {} on {}: {}", code, path, message));
+        }
+        Ok(body)
+    }
+
+    // le backend API ne parle qu'à une socket Unix
+    #[cfg(not(unix))]
+    fn get(&self, _path: &str) -> Result<Vec<u8>, String> {
+        Err(format!("Unable to talk to docker on {}: Unix sockets are not supported on this system", self.socket.display()))
+    }
+}
+
+/// An HTTP answer, headers names are in lower case
+struct HttpResponse<'a> {
+    code: u16,
+    headers: HashMap<String, String>,
+    body: &'a [u8],
+}
+
+/// Split an HTTP response into status code, headers and body
+fn parse_response(response: &[u8]) -> Result<HttpResponse<'_>, String> {
+    let invalid = || String::from("Invalid HTTP answer of the docker engine");
+    let end = response.windows(4).position(|w| w == b"\r\n\r\n").ok_or_else(invalid)?;
+    let head = String::from_utf8_lossy(&response[..end]);
+    let mut lines = head.split("\r\n");
+    let code = lines
+        .next()
+        .and_then(|status| status.split_whitespace().nth(1))
+        .and_then(|c| c.parse::<u16>().ok())
+        .ok_or_else(invalid)?;
+    let headers = lines
+        .filter_map(|l| l.split_once(':'))
+        .map(|(k, v)| (k.trim().to_ascii_lowercase(), v.trim().to_string()))
+        .collect();
+    Ok(HttpResponse { code, headers, body: &response[end + 4..] })
+}
+
+/// Decode a body sent with `Transfer-Encoding: chunked`
+fn dechunk(mut body: &[u8]) -> Result<Vec<u8>, String> {
+    let invalid = || String::from("Invalid chunked answer of the docker engine");
+    let mut decoded = Vec::new();
+    loop {
+        let eol = body.windows(2).position(|w| w == b"\r\n").ok_or_else(invalid)?;
+        let size_line = String::from_utf8_lossy(&body[..eol]);
+        let size_hex = size_line.split(';').next().unwrap_or("").trim();
+        let size = usize::from_str_radix(size_hex, 16).map_err(|_| invalid())?;
+        body = &body[eol + 2..];
+        if size == 0 {
+            return Ok(decoded);
+        }
+        if body.len() < size {
+            return Err(invalid());
+        }
+        decoded.extend_from_slice(&body[..size]);
+        body = body.get(size + 2..).ok_or_else(invalid)?;
+    }
+}
+
+/// Executor that lists containers, images and volumes through the Engine API
+/// and runs every other command with the docker CLI.
+#[cfg(unix)]
+pub struct EngineApiExecutor {
+    client: EngineClient,
+}
+
+#[cfg(unix)]
+impl EngineApiExecutor {
+    pub fn new(client: EngineClient) -> Self {
+        Self { client }
+    }
+}
+
+#[cfg(unix)]
+impl CommandExecutor for EngineApiExecutor {
+    fn execute(&self, command: &[&str]) -> Result<String, String> {
+        RealCommandExecutor.execute(command)
+    }
+
+    fn run(&self, command: &[&str]) -> Result<i32, String> {
+        RealCommandExecutor.run(command)
+    }
+
+    fn stream(&self, command: &[&str], out: &mut dyn std::io::Write) -> Result<i32, String> {
+        RealCommandExecutor.stream(command, out)
+    }
+
+    fn engine(&self) -> Option<&EngineClient> {
+        Some(&self.client)
+    }
+}
+
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    #[cfg(unix)]
+    use std::sync::mpsc;
+
+    /// Serve the canned `answers` (expected path, json body) on a temporary
+    /// socket, one connection per request, and return the socket path with
+    /// the request lines received.
+    #[cfg(unix)]
+    pub fn fake_engine(name: &str, answers: Vec<(&'static str, &'static str)>) -> (PathBuf, mpsc::Receiver<String>) {
+        use std::io::{BufRead, BufReader, Write};
+        use std::os::unix::net::UnixListener;
+        use std::thread;
+        let socket = env::temp_dir().join(format!("dk_engine_{}_{}.sock", name, std::process::id()));
+        let _ = std::fs::remove_file(&socket);
+        let listener = UnixListener::bind(&socket).unwrap();
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            for (path, body) in answers {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut request_line = String::new();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                reader.read_line(&mut request_line).unwrap();
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line == "\r\n" || line.is_empty() {
+                        break;
+                    }
+                }
+                tx.send(request_line.trim().to_string()).unwrap();
+                if request_line.split_whitespace().nth(1) != Some(path) {
+                    let _ = stream.write_all(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n");
+                    continue;
+                }
+                // answer in two chunks like the real daemon does for big lists
+                let (first, second) = body.split_at(body.len() / 2);
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nTransfer-Encoding: chunked\r\n\r\n{:x}\r\n{}\r\n{:x}\r\n{}\r\n0\r\n\r\n",
+                    first.len(), first, second.len(), second
+                );
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+        (socket, rx)
+    }
+
+    pub const CONTAINERS: &str = r#"[{"Id":"3f2b9c1d7e4a5b6c7d8e9f0a1b2c3d4e5f6a7b8c9d0e1f2a3b4c5d6e7f8a9b0c","Names":["/ots_portainer"],"Image":"portainer/portainer-ce:latest","ImageID":"sha256:0f1c","Command":"/portainer","Created":1744618353,"State":"running","Status":"Up 3 hours","Ports":[{"IP":"0.0.0.0","PrivatePort":9000,"PublicPort":9000,"Type":"tcp"},{"PrivatePort":8000,"Type":"tcp"}],"Labels":{}},{"Id":"c9e1f3a2b4d5","Names":["/focused_wozniak"],"Image":"nginx:1.27","Created":1738099029,"State":"created","Status":"Created"}]"#;
+    pub const IMAGES: &str = r#"[{"Id":"sha256:0f1c5a3b2e9d4c7b8a6f5e4d3c2b1a0f9e8d7c6b5a4f3e2d1c0b9a8f7e6d5c4b","RepoTags":["portainer/portainer-ce:latest"],"RepoDigests":["portainer/portainer-ce@sha256:aa"],"Created":1744618353,"Size":301000000},{"Id":"sha256:91d0b8f6c2a4","RepoTags":null,"Created":1738099029,"Size":192000000}]"#;
+    pub const VOLUMES: &str = r#"{"Volumes":[{"Name":"portainer_data","Driver":"local","Mountpoint":"/var/lib/docker/volumes/portainer_data/_data","CreatedAt":"2025-04-14T10:12:33+02:00","Labels":null,"Scope":"local"}],"Warnings":null}"#;
+
+    #[test]
+    #[cfg(unix)]
+    fn test_lists() {
+        let (socket, requests) = fake_engine(
+            "lists",
+            vec![("/containers/json?all=1", CONTAINERS), ("/images/json", IMAGES), ("/volumes", VOLUMES)],
+        );
+        let client = EngineClient::new(socket.clone());
+
+        let containers = client.containers().unwrap();
+        assert_eq!(containers.len(), 2);
+        assert_eq!(containers[0].names, vec!["/ots_portainer"]);
+        assert_eq!(containers[1].status, "Created");
+        assert_eq!(containers[0].ports[0].to_string(), "0.0.0.0:9000->9000/tcp");
+        assert_eq!(containers[0].ports[1].to_string(), "8000/tcp");
+
+        let images = client.images().unwrap();
+        assert_eq!(images[0].repo_tags.as_deref(), Some(&[String::from("portainer/portainer-ce:latest")][..]));
+        assert_eq!(images[1].repo_tags, None);
+        assert_eq!(images[1].size, 192000000);
+
+        let volumes = client.volumes().unwrap();
+        assert_eq!(volumes[0].name, "portainer_data");
+
+        let received: Vec<String> = requests.iter().take(3).collect();
+        assert_eq!(
+            received,
+            vec!["GET /containers/json?all=1 HTTP/1.1", "GET /images/json HTTP/1.1", "GET /volumes HTTP/1.1"]
+        );
+        let _ = std::fs::remove_file(socket);
+    }
+
+    #[test]
+    fn test_parse_response_error() {
+        let response = b"HTTP/1.1 404 Not Found\r\nContent-Length: 29\r\n\r\n{\"message\":\"page not found\"}\n";
+        let parsed = parse_response(response).unwrap();
+        assert_eq!(parsed.code, 404);
+        assert_eq!(parsed.headers.get("content-length").map(|s| s.as_str()), Some("29"));
+        assert!(parsed.body.starts_with(b"{\"message\""));
+    }
+
+    #[test]
+    fn test_dechunk() {
+        assert_eq!(dechunk(b"4\r\nabcd\r\n2;ext=1\r\nef\r\n0\r\n\r\n").unwrap(), b"abcdef");
+        assert!(dechunk(b"zz\r\nabcd\r\n").is_err());
+    }
+
+    #[test]
+    fn test_missing_socket() {
+        let client = EngineClient::new(PathBuf::from("/nonexistent/docker.sock"));
+        assert!(client.containers().unwrap_err().contains("/nonexistent/docker.sock"));
+    }
+}
@@ -0,0 +1,257 @@
+use clap::ValueEnum;
+use regex::Regex;
+use std::fmt;
+use std::sync::LazyLock;
+
+static REGISTRY_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^[a-zA-Z0-9]([a-zA-Z0-9-]*[a-zA-Z0-9])?(\.[a-zA-Z0-9]([a-zA-Z0-9-]*[a-zA-Z0-9])?)*(:[0-9]+)?$").unwrap()
+});
+static COMPONENT_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^[a-z0-9]+((\.|_|__|-+)[a-z0-9]+)*$").unwrap());
+static TAG_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^[A-Za-z0-9_][A-Za-z0-9_.-]{0,127}$").unwrap());
+static DIGEST_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^[a-z0-9]+([.+_-][a-z0-9]+)*:[a-zA-Z0-9=_-]+$").unwrap());
+
+/// Start of every archive name written by `file_name`, the former names never have a `+`
+const FILE_PREFIX: &str = "dk+";
+
+/// Compression used for the image archives
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum ArchiveFormat {
+    #[value(alias = "none")]
+    Tar,
+    #[value(alias = "gz")]
+    Gzip,
+    #[value(alias = "zst")]
+    Zstd,
+}
+
+impl ArchiveFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ArchiveFormat::Tar => "tar",
+            ArchiveFormat::Gzip => "tar.gz",
+            ArchiveFormat::Zstd => "tar.zst",
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ArchiveFormat::Tar => "tar",
+            ArchiveFormat::Gzip => "gzip",
+            ArchiveFormat::Zstd => "zstd",
+        }
+    }
+}
+
+/// A docker image reference: `[registry[:port]/]repository[:tag][@digest]`
+#[derive(Clone, Debug, PartialEq)]
+pub struct ImageRef {
+    pub registry: Option<String>,
+    pub repository: String,
+    pub tag: Option<String>,
+    pub digest: Option<String>,
+}
+
+impl ImageRef {
+    /// Parse an image reference following the docker reference grammar.
+    pub fn parse(reference: &str) -> Result<ImageRef, String> {
+        let invalid = || format!("invalid image reference '{}'", reference);
+
+        let (rest, digest) = match reference.split_once('@') {
+            Some((rest, digest)) => (rest, Some(digest.to_string())),
+            None => (reference, None),
+        };
+        let (path, tag) = match rest.rsplit_once(':') {
+            Some((path, tag)) if !tag.contains('/') => (path, Some(tag.to_string())),
+            _ => (rest, None),
+        };
+        let (registry, repository) = match path.split_once('/') {
+            Some((first, repo))
+                if first.contains('.') || first.contains(':') || first == "localhost" =>
+            {
+                (Some(first.to_string()), repo.to_string())
+            }
+            _ => (None, path.to_string()),
+        };
+
+        let valid = registry.as_ref().is_none_or(|r| REGISTRY_RE.is_match(r))
+            && repository.split('/').all(|c| COMPONENT_RE.is_match(c))
+            && tag.as_ref().is_none_or(|t| TAG_RE.is_match(t))
+            && digest.as_ref().is_none_or(|d| DIGEST_RE.is_match(d));
+        if !valid {
+            return Err(invalid());
+        }
+
+        Ok(ImageRef { registry, repository, tag, digest })
+    }
+
+    /// Name of the image without tag nor digest (`registry/repository`)
+    pub fn name(&self) -> String {
+        match &self.registry {
+            Some(r) => format!("{}/{}", r, self.repository),
+            None => self.repository.clone(),
+        }
+    }
+
+    /// Return the reference with the `latest` tag when neither tag nor digest is given
+    pub fn with_default_tag(mut self) -> ImageRef {
+        if self.tag.is_none() && self.digest.is_none() {
+            self.tag = Some(String::from("latest"));
+        }
+        self
+    }
+
+    /// Map the reference to an archive file name.
+    ///
+    /// The name starts with `dk+`, then `/` becomes `+`, `:` becomes `=`, the
+    /// tag follows `..` and the digest follows `@`, e.g.
+    /// `dk+localhost=5000+team+app..1.0.tar.gz`. None of these sequences can
+    /// appear in a valid reference, so `from_file_name` gives back exactly the
+    /// same reference.
+    pub fn file_name(&self, format: ArchiveFormat) -> String {
+        let mut file = format!("{}{}", FILE_PREFIX, self.name().replace('/', "+").replace(':', "="));
+        if let Some(t) = &self.tag {
+            file.push_str("..");
+            file.push_str(t);
+        }
+        if let Some(d) = &self.digest {
+            file.push('@');
+            file.push_str(&d.replacen(':', "=", 1));
+        }
+        format!("{}.{}", file, format.extension())
+    }
+
+    /// Decode an archive file name produced by `file_name`.
+    ///
+    /// A name without the `dk+` prefix comes from the former naming (`name..tag`
+    /// with `/` replaced by `_`, e.g. `amir20_dozzle..latest.tar.gz`) and is
+    /// decoded that way: a `_` of the repository itself cannot be told apart
+    /// from a `/` there.
+    pub fn from_file_name(file_name: &str) -> Option<(ImageRef, ArchiveFormat)> {
+        let format = [ArchiveFormat::Gzip, ArchiveFormat::Zstd, ArchiveFormat::Tar]
+            .into_iter()
+            .find(|f| file_name.ends_with(&format!(".{}", f.extension())))?;
+        let stem = &file_name[..file_name.len() - format.extension().len() - 1];
+
+        let (rest, digest) = match stem.split_once('@') {
+            Some((rest, d)) => (rest, Some(d.replacen('=', ":", 1))),
+            None => (stem, None),
+        };
+        let (path, tag) = match rest.split_once("..") {
+            Some((path, t)) => (path, Some(t)),
+            None => (rest, None),
+        };
+
+        let mut reference = match path.strip_prefix(FILE_PREFIX) {
+            Some(path) => path.replace('+', "/").replace('=', ":"),
+            None => path.replace('_', "/"),
+        };
+        if let Some(t) = tag {
+            reference.push(':');
+            reference.push_str(t);
+        }
+        if let Some(d) = digest {
+            reference.push('@');
+            reference.push_str(&d);
+        }
+        ImageRef::parse(&reference).ok().map(|r| (r, format))
+    }
+}
+
+impl fmt::Display for ImageRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())?;
+        if let Some(t) = &self.tag {
+            write!(f, ":{}", t)?;
+        }
+        if let Some(d) = &self.digest {
+            write!(f, "@{}", d)?;
+        }
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DIGEST: &str = "sha256:0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef";
+
+    #[test]
+    fn test_parse() {
+        let r = ImageRef::parse("nginx").unwrap();
+        assert_eq!(r.registry, None);
+        assert_eq!(r.repository, "nginx");
+        assert_eq!(r.tag, None);
+
+        let r = ImageRef::parse("localhost:5000/team/app:1.0").unwrap();
+        assert_eq!(r.registry.as_deref(), Some("localhost:5000"));
+        assert_eq!(r.repository, "team/app");
+        assert_eq!(r.tag.as_deref(), Some("1.0"));
+
+        let r = ImageRef::parse(&format!("mcr.microsoft.com/mssql/server:2022-latest@{}", DIGEST)).unwrap();
+        assert_eq!(r.registry.as_deref(), Some("mcr.microsoft.com"));
+        assert_eq!(r.digest.as_deref(), Some(DIGEST));
+
+        assert!(ImageRef::parse("Upper/case").is_err());
+        assert!(ImageRef::parse("app:<none>").is_err());
+        assert!(ImageRef::parse("").is_err());
+    }
+
+    #[test]
+    fn test_file_name() {
+        let r = ImageRef::parse("localhost:5000/team/my_app:1.0").unwrap();
+        assert_eq!(r.file_name(ArchiveFormat::Gzip), "dk+localhost=5000+team+my_app..1.0.tar.gz");
+        let r = ImageRef::parse("nginx").unwrap();
+        assert_eq!(r.file_name(ArchiveFormat::Zstd), "dk+nginx.tar.zst");
+    }
+
+    #[test]
+    fn test_file_name_round_trip() {
+        let references = [
+            "nginx",
+            "nginx:latest",
+            "amir20/dozzle:v8.0.1",
+            "quay.io/vektorlab/ctop:latest",
+            "localhost:5000/team/my_app:1.0",
+            "my_app:1.0",
+            &format!("my_app@{}", DIGEST),
+            "my-registry.local:443/a__b/c.d-e:v1..2",
+            &format!("portainer/portainer-ce@{}", DIGEST),
+            &format!("mcr.microsoft.com/mssql/server:2022-latest@{}", DIGEST),
+        ];
+        for reference in references {
+            let r = ImageRef::parse(reference).unwrap();
+            assert_eq!(r.to_string(), reference);
+            for format in [ArchiveFormat::Tar, ArchiveFormat::Gzip, ArchiveFormat::Zstd] {
+                let file = r.file_name(format);
+                assert!(!file.contains('/') && !file.contains(':'), "{}", file);
+                assert_eq!(ImageRef::from_file_name(&file), Some((r.clone(), format)));
+            }
+        }
+    }
+
+    #[test]
+    fn test_from_file_name_legacy() {
+        // noms écrits avant l'encodage + / =
+        let decode = |file: &str| ImageRef::from_file_name(file).map(|(r, f)| (r.to_string(), f));
+        assert_eq!(
+            decode("mcr.microsoft.com_mssql_server..2022-latest.tar.gz"),
+            Some((String::from("mcr.microsoft.com/mssql/server:2022-latest"), ArchiveFormat::Gzip))
+        );
+        assert_eq!(
+            decode("localhost:5000_team_app..1.0.tar.zst"),
+            Some((String::from("localhost:5000/team/app:1.0"), ArchiveFormat::Zstd))
+        );
+        assert_eq!(decode("nginx..1.27.tar"), Some((String::from("nginx:1.27"), ArchiveFormat::Tar)));
+    }
+
+    #[test]
+    fn test_from_file_name_rejects_unknown_files() {
+        assert_eq!(ImageRef::from_file_name("notes.txt"), None);
+        assert_eq!(ImageRef::from_file_name("Bad Name.tar.gz"), None);
+    }
+}
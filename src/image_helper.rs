@@ -1,189 +1,119 @@
 
-use colored::*;
-use prettytable::{Attr, Cell, Row, Table, color, format};
+use prettytable::{Attr, Cell, Row};
+use flate2::Compression;
+use flate2::write::GzEncoder;
 use std::env;
-use std::process::Command;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::Path;
 use crate::dkutil::*;
+use crate::cli::{self, ArchiveCommand, ImCommand};
 use crate::command_executor::*;
+use crate::image_archive::*;
+use crate::models::*;
+use crate::output::*;
 
 
 
 /// Affiche l'aide pour les commandes liées aux images.
 pub fn usage() {
-    println!("{}", "IMAGES:".cyan());
-    print_colored("(y) . dk im                (w): Show the list of images");
-    print_colored("(y) . dk im rm (b)<images*>   (w): Show the list of images");
-    print_colored("(y) . dk im save (b)<images*> (w): Delete the specified images");
-    print_colored("(y) . dk im load (b)<file*>   (w): Load the specified image files");
-    print_colored("(y) . dk im scan (b)<file*>   (w): Scan images for vulnerabilities");
+    cli::print_usage_section("IMAGES");
 }
 
 /// Tente de récupérer (ou tirer) une image.  
 /// Ici, on recherche d'abord un fichier de sauvegarde, sinon on effectue un docker pull.
-pub fn pull_image(image: &str) {
-    let parts: Vec<&str> = image.split(':').collect();
-    let (name, tag) = if parts.len() == 1 {
-        (parts[0], "latest")
-    } else {
-        (parts[0], parts[1])
+pub fn pull_image(executor: &dyn CommandExecutor, image: &str) {
+    let reference = match ImageRef::parse(image) {
+        Ok(r) => r.with_default_tag(),
+        Err(e) => {
+            print_error(&e);
+            return;
+        }
     };
+    let name = reference.name();
+    let tag = reference.tag.clone().unwrap_or_default();
 
-    if is_image_pulled(name, tag) {
+    if is_image_pulled(executor, &name, &tag) {
         return;
     }
 
     // Construction du nom de fichier de sauvegarde
-    let filename = format!("{name}.{tag}.tar.gz").replace("/", "_");
+    let filename = reference.file_name(ArchiveFormat::Gzip);
     let root_path = env::current_dir().expect("Impossible d'obtenir le répertoire courant");
     let filepath = root_path.join(&filename);
     if filepath.exists() {
         println!("Image found. ({:?})", filepath);
         // Charge l'image via docker load
-        print_and_run(&["docker", "load", "-i", filepath.to_str().unwrap()]);
+        print_and_run(executor, &["docker", "load", "-i", filepath.to_str().unwrap()]);
     } else {
         println!("Image not found. Need to pull it");
-        print_and_run(&["docker", "pull", image]);
+        print_and_run(executor, &["docker", "pull", image]);
     }
     // Sauvegarde l'image pour de futurs usages
-    save_one(name, tag, Some(root_path.to_str().unwrap()));
+    save_one(executor, &reference, Some(root_path.to_str().unwrap()));
 }
 
 /// Retourne true si l'image est déjà présente (recherche via `docker images`).
-pub fn is_image_pulled(image_name: &str, tag: &str) -> bool {
-    let output = Command::new("docker")
-        .arg("images")
-        .output()
-        .expect("Échec de l'exécution de 'docker images'");
-    let stdout = String::from_utf8_lossy(&output.stdout);
-    // Une recherche simpliste dans la sortie
-    stdout.contains(&format!("{}:{}", image_name, tag))
+pub fn is_image_pulled(executor: &dyn CommandExecutor, image_name: &str, tag: &str) -> bool {
+    let image = format!("{}:{}", image_name, tag);
+    get_images(executor).unwrap_or_default().iter().any(|i| i.reference() == image)
 }
 
 /// Récupère la liste des images Docker en format structuré.
-pub fn get_images_with_executor<T: CommandExecutor>(executor: &T) -> Vec<Vec<String>> {
-
-
-    let cmd = vec!["docker", "images", "--format", "{{.ID}}|{{.Repository}}|{{.Tag}}|{{.Size}}|{{.CreatedAt}}"];
-    let output = executor.execute(&cmd).unwrap_or_default();
-    
-    let mut table_data = Vec::new();
-    let mut index = 1;
-    for line in output.lines() {
-        let parts: Vec<&str> = line.split('|').collect();
-        if parts.len() < 5 {
-            continue;
-        }
-        table_data.push(vec![
-            index.to_string(),
-            parts[0].to_string(),
-            parts[1].to_string(),
-            parts[2].to_string(),
-            parts[3].to_string(),
-            parts[4].to_string(),
-        ]);
-        index += 1;
+/// Une erreur quand docker ne peut pas les lister.
+pub fn get_images(executor: &dyn CommandExecutor) -> Result<Vec<Image>, String> {
+    if let Some(engine) = executor.engine() {
+        return engine.images().map(|images| images.into_iter().flat_map(Image::from_summary).collect());
     }
-    table_data
-}
 
-pub fn get_images() -> Vec<Vec<String>> {
-    get_images_with_executor(&RealCommandExecutor)
+    let output = capture(executor, &["docker", "images", "--format", "{{json .}}"])?;
+    parse_json_lines(&output)
 }
 
 /// Affiche la liste des images dans un format tabulaire.
-pub fn show() {
-    let images = get_images();
-    let mut table = Table::new();
-
-    let format = format::FormatBuilder::new()
-        .column_separator(' ') // séparateur de colonne
-        .borders(' ') // bordures
-        .padding(1, 1)
-        .build();
-    table.set_format(format);
-
-    table.set_titles(Row::new(vec![
-        Cell::new("Index")
-            .with_style(Attr::Bold)
-            .with_style(Attr::ForegroundColor(color::GREEN)),
-        Cell::new("ID")
-            .with_style(Attr::Bold)
-            .with_style(Attr::ForegroundColor(color::GREEN)),
-        Cell::new("Name")
-            .with_style(Attr::Bold)
-            .with_style(Attr::ForegroundColor(color::GREEN)),
-        Cell::new("Tag")
-            .with_style(Attr::Bold)
-            .with_style(Attr::ForegroundColor(color::GREEN)),
-        Cell::new("Size")
-            .with_style(Attr::Bold)
-            .with_style(Attr::ForegroundColor(color::GREEN)),
-        Cell::new("Created")
-            .with_style(Attr::Bold)
-            .with_style(Attr::ForegroundColor(color::GREEN)),
-    ]));
+pub fn show(executor: &dyn CommandExecutor, format: OutputFormat) -> i32 {
+    let images = match get_images(executor) {
+        Ok(images) => images,
+        Err(e) => {
+            eprint_error(&e);
+            return 1;
+        }
+    };
+    if let Some(out) = render(&images, format) {
+        print!("{}", out);
+        return 0;
+    }
+    let mut table = new_table(&["Index", "ID", "Name", "Tag", "Size", "Created"]);
 
-    for r in images {
+    for (index, i) in images.iter().enumerate() {
         table.add_row(Row::new(vec![
-            Cell::new(&r[0]),
-            Cell::new(&r[1]),
-            Cell::new(&r[2]).with_style(Attr::Bold),
-            Cell::new(&r[3]),
-            Cell::new(&r[4]),
-            Cell::new(&r[5]),
+            Cell::new(&(index + 1).to_string()),
+            Cell::new(&i.id),
+            Cell::new(&i.repository).with_style(Attr::Bold),
+            Cell::new(&i.tag),
+            Cell::new(&i.size),
+            Cell::new(&i.created),
         ]));
     }
 
-    table.printstd();
+    print_table(&table);
+    0
 }
 
 /// Handle the command 'im'
-pub fn cmd(arguments: &[String]) ->i32 {
-    if arguments.is_empty() {
-        show();
-        return 1;
-    }
-    let command = &arguments[0];
-    let args = &arguments[1..];
-    match command.as_str() {
-        "rm" => {
-            if args.is_empty() {
-                println!("Error: 'rm' command requires at least one image");
-                return 1;
-            }
-            remove(args);
-        }
-        "save" => {
-            if args.is_empty() {
-                println!("Error: 'save' command requires at least one image");
-                return 1;
-            }
-            save(args);
-        }
-        "load" => {
-            if args.is_empty() {
-                println!("Error: 'load' command requires at least one file");
-                return 1;
-            }
-            load(args);
-        }
-        "scan" => {
-            if args.is_empty() {
-                println!("Error: 'scan' command requires at least one image");
-                return 1;
-            }
-            scan(args);
-        }
-        _ => {
-            print_error("unknown command");
-            
-        }
+pub fn cmd(executor: &dyn CommandExecutor, output: OutputFormat, command: Option<ImCommand>) -> i32 {
+    match command {
+        None => show(executor, output),
+        Some(ImCommand::Rm { images }) => remove(executor, &images),
+        Some(ImCommand::Save { format, images }) => save(executor, &images, format),
+        Some(ImCommand::Load { files }) => load(executor, &files),
+        Some(ImCommand::Scan { images }) => scan(executor, &images),
+        Some(ImCommand::Archive { command: ArchiveCommand::Ls { dir } }) => archive_ls(executor, &dir),
     }
-    return 0;
 }
 
 
-/// Translates filters to Docker image IDs.
+/// Translates filters to Docker image references.
 ///
 /// # Arguments
 ///
@@ -191,27 +121,24 @@ pub fn cmd(arguments: &[String]) ->i32 {
 ///
 /// # Returns
 ///
-/// A vector of strings containing the actual Docker image IDs.
+/// A vector of strings containing the image references (`repository:tag`) or IDs.
 ///
 /// # Details
 ///
 /// For each filter:
-/// - If the filter is a valid rank (numeric index), converts it to the corresponding image ID
+/// - If the filter is a valid rank (numeric index), converts it to the corresponding image reference
 /// - If the filter is not a valid rank, assumes it's already an image ID and returns it as-is
 ///
 /// Requires `get_images()` to retrieve the list of available Docker images.
-fn translate_to_id(filters: &[String]) -> Vec<String> {
-    let images = get_images();
-    let max_rank = images.iter().count();
-    
-    filters.iter().map(|f| {
-        if is_valid_rank(f, max_rank) {
-            let row = &images[f.parse::<usize>().unwrap() - 1];
-            row[2].as_str().to_owned() + ":" + row[3].as_str()
-        } else {
-            f.to_string()
-        }
-    }).collect()
+fn translate_to_id(executor: &dyn CommandExecutor, filters: &[String]) -> Vec<String> {
+    let images = get_images(executor).unwrap_or_else(|e| {
+        print_error(&e);
+        Vec::new()
+    });
+    // une image sans nom (<none>:<none>) ne peut être désignée que par son ID
+    translate_ranks(&images, filters, |i| {
+        if i.repository == "<none>" || i.tag == "<none>" { i.id.clone() } else { i.reference() }
+    })
 }
 
 
@@ -221,40 +148,156 @@ fn translate_to_id(filters: &[String]) -> Vec<String> {
 ///
 /// * `filters` - A slice of strings that can be either image ranks or image IDs
 ///
-fn remove(filters: &[String])
+fn remove(executor: &dyn CommandExecutor, filters: &[String]) -> i32
 {
-    let image_ids = translate_to_id(filters);
+    let image_ids = translate_to_id(executor, filters);
+    let mut retcode = 0;
     
     for image_id in image_ids {
         print_info(&format!("Removing image {}", image_id));
-        print_and_run(&["docker", "rmi", &image_id]);
+        if print_and_run(executor, &["docker", "rmi", &image_id]) != 0 {
+            retcode = 1;
+        }
     }
+    retcode
 }
 
 
-fn save(filters: &[String]) {
-    let images = translate_to_id(filters);
-    for image in images {
-        print_info(&format!("Saving image {}", image));
-        let mut imagefile=image.replace(":","..");
-        imagefile=imagefile.replace("/","_");
-        // À implémenter : utiliser "docker save" puis compresser en gzip.
-        // Pour l'instant, on se contente d'afficher la commande.
-        print_and_run(&["docker", "save", &image, "-o", &format!("{}.tar", imagefile)]);
+fn save(executor: &dyn CommandExecutor, filters: &[String], format: ArchiveFormat) -> i32 {
+    let mut retcode = 0;
+    for image in translate_to_id(executor, filters) {
+        let filename = match ImageRef::parse(&image) {
+            Ok(r) => r.file_name(format),
+            Err(e) => {
+                print_error(&e);
+                retcode = 1;
+                continue;
+            }
+        };
+        print_info(&format!("Saving image {} into {}", image, filename));
+        if let Err(e) = save_archive(executor, &image, Path::new(&filename), format) {
+            print_error(&format!("Unable to save image {} : {}", image, e));
+            retcode = 1;
+        }
     }
+    retcode
 }
 
-fn load(filters: &[String]) {
-    for file in filters {
-        print_info(&format!("Loading image file {}", file));
-        print_and_run(&["docker", "load", "-i", file]);
+/// Stream the output of `docker save` through the compressor into `path`.
+///
+/// The partial file is removed when something goes wrong.
+fn save_archive(
+    executor: &dyn CommandExecutor,
+    image: &str,
+    path: &Path,
+    format: ArchiveFormat,
+) -> Result<(), String> {
+    let result = write_archive(executor, image, path, format);
+    if result.is_err() {
+        let _ = fs::remove_file(path);
     }
+    result
 }
 
-fn scan(filters: &[String]) {
+fn write_archive(
+    executor: &dyn CommandExecutor,
+    image: &str,
+    path: &Path,
+    format: ArchiveFormat,
+) -> Result<(), String> {
+    print_info(&format!("docker save {} | {} > {}", image, format.name(), path.display()));
+
+    let cmd = ["docker", "save", image];
+    let mut file = File::create(path).map_err(|e| e.to_string())?;
+    let code = match format {
+        ArchiveFormat::Tar => {
+            let code = executor.stream(&cmd, &mut file)?;
+            file.flush().map_err(|e| e.to_string())?;
+            code
+        }
+        ArchiveFormat::Gzip => {
+            let mut encoder = GzEncoder::new(file, Compression::default());
+            let code = executor.stream(&cmd, &mut encoder)?;
+            encoder.finish().and_then(|mut f| f.flush()).map_err(|e| e.to_string())?;
+            code
+        }
+        ArchiveFormat::Zstd => {
+            let mut encoder = zstd::Encoder::new(file, 0).map_err(|e| e.to_string())?;
+            let code = executor.stream(&cmd, &mut encoder)?;
+            encoder.finish().and_then(|mut f| f.flush()).map_err(|e| e.to_string())?;
+            code
+        }
+    };
+    if code != 0 {
+        return Err(format!("docker save exited with code {}", code));
+    }
+    Ok(())
+}
+
+fn load(executor: &dyn CommandExecutor, files: &[String]) -> i32 {
+    let mut retcode = 0;
+    for file in files {
+        let file_name = Path::new(file).file_name().and_then(|f| f.to_str()).unwrap_or(file);
+        match ImageRef::from_file_name(file_name) {
+            Some((reference, _)) => print_info(&format!("Loading image {} from {}", reference, file)),
+            None => print_info(&format!("Loading image file {} (unknown image)", file)),
+        }
+        if print_and_run(executor, &["docker", "load", "-i", file]) != 0 {
+            retcode = 1;
+        }
+    }
+    retcode
+}
+
+/// List the image archives of a directory with the image each one contains
+fn archive_ls(executor: &dyn CommandExecutor, dir: &str) -> i32 {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            print_error(&format!("Unable to read directory {} : {}", dir, e));
+            return 1;
+        }
+    };
+    let mut archives: Vec<(String, ImageRef, ArchiveFormat, u64)> = entries
+        .filter_map(|e| e.ok())
+        .filter_map(|e| {
+            let file = e.file_name().to_string_lossy().to_string();
+            let (reference, format) = ImageRef::from_file_name(&file)?;
+            let size = e.metadata().map(|m| m.len()).unwrap_or(0);
+            Some((file, reference, format, size))
+        })
+        .collect();
+    archives.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let images: Vec<String> = match get_images(executor) {
+        Ok(images) => images.iter().map(|i| i.reference()).collect(),
+        Err(e) => {
+            eprint_error(&e);
+            return 1;
+        }
+    };
+
+    let mut table = new_table(&["Index", "File", "Image", "Format", "Size", "Loaded"]);
+    for (index, (file, reference, archive_format, size)) in archives.iter().enumerate() {
+        let loaded = images.contains(&reference.clone().with_default_tag().to_string());
+        table.add_row(Row::new(vec![
+            Cell::new(&(index + 1).to_string()),
+            Cell::new(file),
+            Cell::new(&reference.to_string()).with_style(Attr::Bold),
+            Cell::new(archive_format.name()),
+            Cell::new(&format_size(*size)),
+            Cell::new(if loaded { "yes" } else { "no" }),
+        ]));
+    }
+    print_table(&table);
+    0
+}
+
+fn scan(executor: &dyn CommandExecutor, filters: &[String]) -> i32 {
+    let mut retcode = 0;
     for image in filters {
         print_info(&format!("Scanning image {}", image));
-        print_and_run(&[
+        let code = print_and_run(executor, &[
             "docker",
             "run",
             "--tty",
@@ -269,21 +312,21 @@ fn scan(filters: &[String]) {
             "/cachedata",
             image,
         ]);
+        if code != 0 {
+            retcode = 1;
+        }
     }
+    retcode
 }
 
 /// Sauvegarde une image (fonction interne).
-fn save_one(name: &str, tag: &str, path: Option<&str>) {
-    let full_image_name = format!("{}:{}", name, tag);
-    let mut filename = format!("{}.{}.tar.gz", name, tag).replace("/", "_");
-    print_info(&format!("saving image {} into {}", name, filename));
+fn save_one(executor: &dyn CommandExecutor, reference: &ImageRef, path: Option<&str>) {
+    let mut filename = reference.file_name(ArchiveFormat::Gzip);
+    print_info(&format!("saving image {} into {}", reference, filename));
     if let Some(p) = path {
         filename = format!("{}/{}", p, filename);
     }
-    // À implémenter : appeler "docker save" et compresser
-    print_and_run(&["docker", "save", &full_image_name, "-o", &filename]);
+    if let Err(e) = save_archive(executor, &reference.to_string(), Path::new(&filename), ArchiveFormat::Gzip) {
+        print_error(&format!("Unable to save image {} : {}", reference, e));
+    }
 }
-
-
-
-
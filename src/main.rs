@@ -1,91 +1,145 @@
 use colored::*;
 use std::env;
 use rustc_version::*;
+use command_executor::*;
+use cli::{Cli, Commands, OtsCommand};
+use clap::Parser;
+use dkutil::{print_colored, print_error};
+pub mod alias_helper;
+pub mod cli;
 pub mod container_helper;
 pub mod dkutil;
+pub mod engine_api;
+pub mod image_archive;
 pub mod image_helper;
+pub mod models;
+pub mod output;
 pub mod ots_helper;
 pub mod ports;
+pub mod readiness;
 pub mod volume_helper;
 pub mod system_helper;
+pub mod variables;
 pub mod command_executor;
+pub mod completion;
+pub mod compose;
 pub mod config;
+pub mod config_helper;
+pub mod config_format;
+pub mod config_layers;
 
 #[warn(unused_imports)]
-fn main() ->() {
+fn main() {
     let args: Vec<String> = env::args().collect();
-    
-    //  let args: Vec<String> = vec!["dk", "im","save","6"]
-    //  .into_iter()
-    //  .map(String::from)
-    //  .collect();
+    let exitcode = match create_executor() {
+        Ok(executor) => run(executor.as_ref(), &args),
+        Err(e) => {
+            dkutil::print_error(&e);
+            1
+        }
+    };
+    std::process::exit(exitcode);
+}
 
-    
-    if args.len() == 1 {
-        show_usage();
-        std::process::exit(1);
+/// Choose the docker backend: the CLI (default) or the Engine API when `DK_BACKEND=api`
+fn create_executor() -> Result<Box<dyn CommandExecutor>, String> {
+    match env::var("DK_BACKEND").unwrap_or_default().as_str() {
+        "" | "cli" => Ok(Box::new(RealCommandExecutor)),
+        #[cfg(unix)]
+        "api" => Ok(Box::new(engine_api::EngineApiExecutor::new(engine_api::EngineClient::from_env()?))),
+        #[cfg(not(unix))]
+        "api" => Err(String::from("DK_BACKEND=api needs the docker Unix socket, use the cli backend on this system")),
+        other => Err(format!("Unknown DK_BACKEND '{}' (expected cli or api)", other)),
     }
-    
-    let command = args[1].as_str();
-    let arguments = &args[2..];
-    let mut exitcode=1;
-    match command {
-        "shell" => {
-            if arguments.len() != 1 {
-                println!("Error: 'shell' command takes one argument");
-            } else {
-                exitcode=container_helper::exec_shell(&arguments[0]);
-            }
-        }
-        "ps" => {
-            if !arguments.is_empty() {
-                println!("Error: 'ps' command does not take any arguments");
-            } else {
-                container_helper::show();
-                exitcode=0;
-            }
-        }
-        "rm" => {
-            if arguments.is_empty() {
-                println!("Error: 'rm' command requires at least one container");
-            } else {
-                container_helper::remove(arguments);
-                exitcode=0;
-            }
-        }
-        "trunclog" => {
-            println!("'trunclog' not yet implemented");
-        }
-        "sys" => {
-            exitcode=system_helper::cmd(arguments);
-        }
+}
 
-        "ots" => {
-            exitcode = ots_helper::cmd(arguments);
+/// Run the command line `args` (including the program name) and return the exit code,
+/// an alias of the configuration is expanded first
+fn run(executor: &dyn CommandExecutor, args: &[String]) -> i32 {
+    // la configuration n'est lue que si la commande n'est pas une commande de dk ;
+    // si elle est illisible, clap signale la commande inconnue
+    let word = args.get(1..).and_then(alias_helper::command_word).map(|i| &args[i + 1]);
+    if let Some(word) = word
+        && !cli::command_names().contains(word)
+        && let Ok(config) = ots_helper::get_config()
+        && config.aliases.contains_key(word)
+    {
+        return alias_helper::run(&config, args, &|line| run_command(executor, line));
+    }
+    run_command(executor, args)
+}
+
+/// Parse the command line `args` and dispatch it to the helpers
+fn run_command(executor: &dyn CommandExecutor, args: &[String]) -> i32 {
+    let cli = match Cli::try_parse_from(args) {
+        Ok(cli) => cli,
+        Err(e) => {
+            // aide, version ou erreur de syntaxe (code 2)
+            let _ = e.print();
+            return e.exit_code();
         }
+    };
 
+    // option globale : --no-color (ou la variable d'environnement NO_COLOR)
+    if cli.no_color || env::var("NO_COLOR").is_ok_and(|v| !v.is_empty()) {
+        dkutil::set_color(false);
+    }
 
-        
-        "vol" => {
-            volume_helper::cmd(arguments);
-            exitcode=0;
+    let Some(command) = cli.command else {
+        show_usage();
+        return 1;
+    };
+
+    match command {
+        Commands::Ps { output } => container_helper::show(executor, output),
+        Commands::Rm { containers } => container_helper::remove(executor, &containers),
+        Commands::Shell { container } => container_helper::exec_shell(executor, &container),
+        Commands::Trunclog { keep, containers } => {
+            container_helper::trunclog(executor, &containers, keep.unwrap_or(0))
         }
-        
-        "im" => {
-            exitcode=image_helper::cmd(arguments);            
+        Commands::Im { output, command } => image_helper::cmd(executor, output, command),
+        Commands::Vol { output, command } => volume_helper::cmd(executor, output, command),
+        Commands::Sys { command } => system_helper::cmd(executor, command),
+        // une configuration invalide ne peut être que corrigée avec dk ots edit
+        Commands::Ots { command: Some(OtsCommand::Edit) } => ots_helper::edit(executor),
+        Commands::Ots { command } => match ots_helper::get_config() {
+            Ok(config) => ots_helper::cmd(executor, &config, command),
+            Err(e) => {
+                print_error(&e);
+                1
+            }
+        },
+        Commands::Config { command } => config_helper::cmd(command),
+        Commands::Alias { command } => match ots_helper::get_config() {
+            Ok(config) => alias_helper::cmd(&config, command),
+            Err(e) => {
+                print_error(&e);
+                1
+            }
+        },
+        Commands::Completions { shell } => {
+            print!("{}", completion::script(shell));
+            0
+        }
+        Commands::Complete { words } => {
+            // la sortie est lue par le shell : ni couleurs ni messages
+            dkutil::set_color(false);
+            dkutil::set_quiet(true);
+            let config = ots_helper::get_config().unwrap_or_default();
+            for word in completion::complete(executor, &config, &words) {
+                println!("{}", word);
+            }
+            0
         }
-        
-        _ => println!("Error: Unknown command '{}'", command),
     }
-
-    std::process::exit(exitcode);
 }
 
 
 fn show_usage() {
     let version = version().unwrap();
-    let usage=format!("dk version 4.4 - G. Singer 2018-2025 (built with Rust {})",version);
+    let usage=format!("dk version {} - G. Singer 2018-2025 (built with Rust {})", env!("CARGO_PKG_VERSION"), version);
     println!("{}", usage.bright_magenta() );
+    print_colored("(y)Global options: (b)--no-color (w)(or the NO_COLOR environment variable), (b)--help (w)on any command");
     
     container_helper::usage();
     println!();
@@ -96,5 +150,154 @@ fn show_usage() {
     system_helper::usage();
     println!();
     ots_helper::usage();
+    println!();
+    config_helper::usage();
+    println!();
+    alias_helper::usage();
+    println!();
+    completion::usage();
 }
 
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn test_ps() {
+        let mut mock = MockCommandExecutor::new();
+        mock.expect(&["docker", "ps", "..."], MockResponse::fixture("docker_ps.txt"));
+
+        assert_eq!(run(&mock, &args("dk ps")), 0);
+        assert_eq!(mock.command_lines(), vec!["docker ps -a --format {{json .}}"]);
+    }
+
+    #[test]
+    fn test_im_rm() {
+        let mut mock = MockCommandExecutor::new();
+        mock.expect(&["docker", "images", "..."], MockResponse::fixture("docker_images.txt"))
+            .expect(&["docker", "rmi", "*"], MockResponse::ok(""));
+
+        assert_eq!(run(&mock, &args("dk im rm 3")), 0);
+        assert_eq!(
+            mock.command_lines()[1],
+            "docker rmi mcr.microsoft.com/mssql/server:2022-latest"
+        );
+        assert_eq!(mock.calls().len(), 2);
+    }
+
+    #[test]
+    fn test_im_rm_dangling_image() {
+        let mut mock = MockCommandExecutor::new();
+        mock.expect(
+            &["docker", "images", "..."],
+            MockResponse::ok(r#"{"ID":"5d3e1b7a9c20","Repository":"<none>","Tag":"<none>","Size":"12MB"}"#),
+        )
+        .expect(&["docker", "rmi", "*"], MockResponse::ok(""));
+
+        assert_eq!(run(&mock, &args("dk im rm 1")), 0);
+        assert_eq!(mock.command_lines()[1], "docker rmi 5d3e1b7a9c20");
+    }
+
+    #[test]
+    fn test_sys_prune_failure_is_reported() {
+        let mut mock = MockCommandExecutor::new();
+        mock.expect(&["docker", "network", "prune", "-f"], MockResponse::ok(""))
+            .expect(&["docker", "volume", "prune", "-f"], MockResponse::fail(1, "daemon error"))
+            .expect(&["docker", "buildx", "prune", "-f"], MockResponse::ok(""));
+
+        assert_eq!(run(&mock, &args("dk sys prune")), 1);
+        assert_eq!(mock.calls().len(), 3);
+    }
+
+    #[test]
+    fn test_vol_rm() {
+        let mut mock = MockCommandExecutor::new();
+        mock.expect(&["docker", "volume", "ls", "..."], MockResponse::fixture("docker_volume_ls.txt"))
+            .expect(&["docker", "volume", "rm", "*"], MockResponse::ok(""));
+
+        assert_eq!(run(&mock, &args("dk vol rm 2")), 0);
+        assert_eq!(
+            mock.command_lines(),
+            vec!["docker volume ls --format {{json .}}", "docker volume rm sqlserver_data"]
+        );
+    }
+
+    #[test]
+    fn test_rm_by_name_and_rank() {
+        let mut mock = MockCommandExecutor::new();
+        mock.expect(&["docker", "ps", "..."], MockResponse::fixture("docker_ps.txt"))
+            .expect(&["docker", "rm", "-f", "*"], MockResponse::ok(""));
+
+        assert_eq!(run(&mock, &args("dk rm 4 ots_kroki")), 0);
+        assert_eq!(
+            mock.command_lines()[1..],
+            ["docker rm -f focused_wozniak", "docker rm -f ots_kroki"]
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_ps_with_engine_api() {
+        use engine_api::tests::*;
+        let (socket, requests) = fake_engine(
+            "ps",
+            vec![("/containers/json?all=1", CONTAINERS), ("/containers/json?all=1", CONTAINERS)],
+        );
+        let executor = engine_api::EngineApiExecutor::new(engine_api::EngineClient::new(socket.clone()));
+
+        assert_eq!(run(&executor, &args("dk ps")), 0);
+        assert_eq!(requests.recv().unwrap(), "GET /containers/json?all=1 HTTP/1.1");
+        let containers = container_helper::get_containers(&executor).unwrap();
+        let names: Vec<&str> = containers.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, vec!["ots_portainer", "focused_wozniak"]);
+        assert_eq!(containers[0].id, "3f2b9c1d7e4a");
+        assert_eq!(containers[0].ports, "0.0.0.0:9000->9000/tcp, 8000/tcp");
+        let _ = std::fs::remove_file(socket);
+    }
+
+    #[test]
+    fn test_output_options() {
+        let mut mock = MockCommandExecutor::new();
+        mock.expect(&["docker", "images", "..."], MockResponse::fixture("docker_images.txt"))
+            .expect(&["docker", "volume", "ls", "..."], MockResponse::fixture("docker_volume_ls.txt"))
+            .expect(&["docker", "ps", "..."], MockResponse::fixture("docker_ps.txt"));
+
+        assert_eq!(run(&mock, &args("dk im -o csv")), 0);
+        assert_eq!(run(&mock, &args("dk vol --output yaml")), 0);
+        assert_eq!(run(&mock, &args("dk ps -o json")), 0);
+        assert_eq!(run(&mock, &args("dk ps -o xml")), 2);
+        assert_eq!(run(&mock, &args("dk ps extra")), 2);
+        assert_eq!(run(&mock, &args("dk ps --help")), 0);
+        assert_eq!(mock.calls().len(), 3);
+    }
+
+    #[test]
+    fn test_completions() {
+        let mock = MockCommandExecutor::new();
+        assert_eq!(run(&mock, &args("dk completions bash")), 0);
+        assert_eq!(run(&mock, &args("dk completions tcsh")), 2);
+        assert!(mock.calls().is_empty());
+    }
+
+    #[test]
+    fn test_docker_failure_is_reported() {
+        let mut mock = MockCommandExecutor::new();
+        mock.expect(&["docker", "ps", "..."], MockResponse::fixture("docker_ps.txt"))
+            .expect(&["docker", "rm", "-f", "*"], MockResponse::fail(1, "No such container"));
+
+        assert_eq!(run(&mock, &args("dk rm missing")), 1);
+
+        // une liste illisible n'est pas une liste vide
+        let mut mock = MockCommandExecutor::new();
+        mock.expect(&["docker", "..."], MockResponse::fail(1, "Cannot connect to the Docker daemon"));
+        assert_eq!(run(&mock, &args("dk ps -o json")), 1);
+        assert_eq!(run(&mock, &args("dk im")), 1);
+        assert_eq!(run(&mock, &args("dk vol -o csv")), 1);
+    }
+}
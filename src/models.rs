@@ -0,0 +1,150 @@
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use crate::dkutil::*;
+use crate::engine_api::{ContainerSummary, ImageSummary, VolumeSummary};
+
+/// A docker container, as listed by `docker ps -a --format '{{json .}}'`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct Container {
+    #[serde(rename(deserialize = "ID"))]
+    pub id: String,
+    #[serde(rename(deserialize = "Names"))]
+    pub name: String,
+    #[serde(rename(deserialize = "Image"))]
+    pub image: String,
+    #[serde(rename(deserialize = "State"), default)]
+    pub state: String,
+    #[serde(rename(deserialize = "Status"), default)]
+    pub status: String,
+    #[serde(rename(deserialize = "Ports"), default)]
+    pub ports: String,
+    #[serde(rename(deserialize = "CreatedAt"), default)]
+    pub created: String,
+}
+
+/// A docker image, as listed by `docker images --format '{{json .}}'`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct Image {
+    #[serde(rename(deserialize = "ID"))]
+    pub id: String,
+    #[serde(rename(deserialize = "Repository"))]
+    pub repository: String,
+    #[serde(rename(deserialize = "Tag"))]
+    pub tag: String,
+    #[serde(rename(deserialize = "Size"), default)]
+    pub size: String,
+    #[serde(rename(deserialize = "CreatedAt"), default)]
+    pub created: String,
+}
+
+/// A docker volume, as listed by `docker volume ls --format '{{json .}}'`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct Volume {
+    #[serde(rename(deserialize = "Name"))]
+    pub name: String,
+    #[serde(rename(deserialize = "Driver"), default)]
+    pub driver: String,
+    #[serde(rename(deserialize = "Mountpoint"), default)]
+    pub mountpoint: String,
+}
+
+impl Image {
+    /// `repository:tag` reference of the image
+    pub fn reference(&self) -> String {
+        format!("{}:{}", self.repository, self.tag)
+    }
+
+    /// One image per tag, like the docker CLI does
+    pub fn from_summary(summary: ImageSummary) -> Vec<Image> {
+        let tags = summary
+            .repo_tags
+            .filter(|t| !t.is_empty())
+            .unwrap_or_else(|| vec![String::from("<none>:<none>")]);
+        tags.iter()
+            .map(|repo_tag| {
+                let (repository, tag) = repo_tag.rsplit_once(':').unwrap_or((repo_tag, "<none>"));
+                Image {
+                    id: short_id(&summary.id),
+                    repository: repository.to_string(),
+                    tag: tag.to_string(),
+                    size: format_docker_size(summary.size.max(0) as u64),
+                    created: format_timestamp(summary.created),
+                }
+            })
+            .collect()
+    }
+}
+
+impl From<ContainerSummary> for Container {
+    fn from(c: ContainerSummary) -> Self {
+        let names: Vec<&str> = c.names.iter().map(|n| n.trim_start_matches('/')).collect();
+        let ports: Vec<String> = c.ports.iter().map(|p| p.to_string()).collect();
+        Container {
+            id: short_id(&c.id),
+            name: names.join(","),
+            image: c.image,
+            state: c.state,
+            status: c.status,
+            ports: ports.join(", "),
+            created: format_timestamp(c.created),
+        }
+    }
+}
+
+impl From<VolumeSummary> for Volume {
+    fn from(v: VolumeSummary) -> Self {
+        Volume {
+            name: v.name,
+            driver: v.driver,
+            mountpoint: v.mountpoint,
+        }
+    }
+}
+
+/// Parse the output of a docker command run with `--format '{{json .}}'`
+/// (one JSON object per line)
+pub fn parse_json_lines<T: DeserializeOwned>(output: &str) -> Result<Vec<T>, String> {
+    output
+        .lines()
+        .filter(|l| !l.trim().is_empty())
+        .map(|l| serde_json::from_str(l).map_err(|e| format!("Unable to parse docker output: {}", e)))
+        .collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_containers() {
+        let output = r#"{"Command":"\"/portainer\"","CreatedAt":"2025-04-14 10:12:33 +0200 CEST","ID":"3f2b9c1d7e4a","Image":"portainer/portainer-ce:latest","Labels":"team=a|b","Names":"ots_portainer","Ports":"0.0.0.0:9000->9000/tcp","State":"running","Status":"Up 3 hours (healthy)"}
+"#;
+        let containers: Vec<Container> = parse_json_lines(output).unwrap();
+        assert_eq!(containers.len(), 1);
+        assert_eq!(containers[0].name, "ots_portainer");
+        assert_eq!(containers[0].id, "3f2b9c1d7e4a");
+        assert_eq!(containers[0].status, "Up 3 hours (healthy)");
+    }
+
+    #[test]
+    fn test_parse_invalid_output() {
+        assert!(parse_json_lines::<Volume>("ots_rabbitmq\n").is_err());
+    }
+
+    #[test]
+    fn test_image_from_summary() {
+        let summary = ImageSummary {
+            id: String::from("sha256:91d0b8f6c2a4e5f6"),
+            repo_tags: Some(vec![String::from("localhost:5000/app:1.0"), String::from("app:latest")]),
+            repo_digests: None,
+            created: 0,
+            size: 192_000_000,
+        };
+        let images = Image::from_summary(summary);
+        assert_eq!(images.len(), 2);
+        assert_eq!(images[0].reference(), "localhost:5000/app:1.0");
+        assert_eq!(images[0].id, "91d0b8f6c2a4");
+        assert_eq!(images[1].size, "192MB");
+    }
+}
@@ -1,99 +1,790 @@
 
+use prettytable::{Attr, Cell, Row};
+use serde::Serialize;
+use crate::cli::{self, OtsAddArgs, OtsCommand};
+use crate::command_executor::*;
+use crate::container_helper::{self, get_containers};
 use crate::dkutil::*;
-use colored::*;
 use crate::config::*;
+use crate::models::Container;
+use crate::compose;
+use crate::config_format::ConfigFormat;
+use crate::config_layers::{self, LayeredConfig};
+use crate::readiness;
+use serde_json::Value;
+use std::{env, fs, io};
+use std::path::Path;
+use crate::variables::{Variables, SECRETS_FILE_NAME};
+use std::time::{Duration, Instant};
+use crate::output::*;
+
+/// A service of the configuration with the live state of its container
+#[derive(Serialize, Debug, PartialEq, Default)]
+pub struct OtsStatus {
+    pub name: String,
+    pub image: String,
+    pub port: Option<u16>,
+    pub container: String,
+    pub exists: bool,
+    pub state: String,
+    pub health: String,
+    pub uptime: String,
+    pub url: String,
+}
 
 pub fn usage() {
-    let _config=get_config();
-    println!("{}", "OTS:".cyan());
-    print_colored("(y) . ots up (b)<image>       (w): Create and run a container based on the specified image");    
-    print_colored("(y) . ots down (b)<image>     (w): Delete the specified container");
+    match get_config() {
+        Ok(config) => print_usage(&config),
+        Err(e) => {
+            cli::print_usage_section("OTS");
+            print_error(&e);
+        }
+    }
+}
+
+fn print_usage(config: &DkConfig) {
+    cli::print_usage_section("OTS");
 
     let mut ots_list = String::new();
-    for c in _config.ots {
+    for c in &config.ots {
         ots_list.push_str(&c.name);
-        ots_list.push_str("|");
+        ots_list.push('|');
     }
     ots_list.pop();
     let ots=format!("(y) The supported ots are  (w): (b){}",ots_list);
 
     print_colored(&ots);
-    print_colored("                         (y) (see ~/.dk.dk_config.json)");
+    for (profile, services) in &config.profiles {
+        print_colored(&format!("(y) Profile (b)@{} (w): {}", profile, services.join(", ")));
+    }
+    // les fichiers réellement lus : DK_CONFIG ou ~/.dk, puis le projet
+    let mut files: Vec<String> = DkConfig::get_config_path().into_iter().map(|p| p.display().to_string()).collect();
+    files.extend(config_layers::project_file().map(|p| p.display().to_string()));
+    if !files.is_empty() {
+        print_colored(&format!("                         (y) (see {})", files.join(", ")));
+    }
 }
 
-pub fn cmd(arguments: &[String]) -> i32 {
-    if arguments.is_empty() {
-        usage();
-        return 0;
+pub fn cmd(executor: &dyn CommandExecutor, config: &DkConfig, command: Option<OtsCommand>) -> i32 {
+    // vérification automatique de la configuration, sans bloquer la commande
+    // les commandes qui modifient la configuration la relisent et la vérifient elles-mêmes
+    let edits = matches!(
+        command,
+        Some(OtsCommand::Add(_) | OtsCommand::Edit | OtsCommand::Remove { .. } | OtsCommand::Rename { .. } | OtsCommand::Import { .. })
+    );
+    if !edits && !matches!(command, None | Some(OtsCommand::Check)) {
+        for problem in config.check() {
+            print_warning(&format!("Configuration: {}", problem));
+        }
+    }
+    match command {
+        None => {
+            print_usage(config);
+            0
+        }
+        Some(OtsCommand::Ls { output }) => show(executor, config, output),
+        Some(OtsCommand::Check) => check(config),
+        Some(OtsCommand::Show { name }) => show_service(config, &name),
+        Some(OtsCommand::Import { file }) => import(&file),
+        Some(OtsCommand::Add(args)) => add(executor, args),
+        Some(OtsCommand::Edit) => edit(executor),
+        Some(OtsCommand::Remove { names }) => update_config(|config| {
+            config.remove(&names)?;
+            Ok(format!("Removed {}", names.join(", ")))
+        }),
+        Some(OtsCommand::Rename { old, new }) => rename(executor, config, &old, &new),
+        Some(OtsCommand::Export { names }) => export(config, &names),
+        Some(OtsCommand::Up { names, wait, timeout }) => with_services(config.start_order(&names), |names| {
+            up_container(executor, config, names, wait.then_some(timeout))
+        }),
+        Some(OtsCommand::Down { names, with_deps }) => with_services(stop_order(config, &names, with_deps), |names| {
+            container_command(executor, config, names, &["rm", "-f"], "Stopping and removing")
+        }),
+        Some(OtsCommand::Start { names }) => with_services(config.start_order(&names), |names| {
+            container_command(executor, config, names, &["start"], "Starting")
+        }),
+        Some(OtsCommand::Stop { names, with_deps }) => with_services(stop_order(config, &names, with_deps), |names| {
+            container_command(executor, config, names, &["stop"], "Stopping")
+        }),
+        Some(OtsCommand::Restart { names }) => with_services(config.expand(&names), |names| {
+            container_command(executor, config, names, &["restart"], "Restarting")
+        }),
+        Some(OtsCommand::Logs { name, tail }) => {
+            let tail = tail.to_string();
+            container_command(executor, config, &[name], &["logs", "-f", "--tail", &tail], "Following the logs of")
+        }
+        Some(OtsCommand::Shell { name }) => shell(executor, config, &name),
+        Some(OtsCommand::Upgrade { all, names }) => upgrade(executor, config, &names, all),
     }
-    let command = &arguments[0];
-    match command.as_str() {
-        "up" => up_container(&arguments[1..]),
-        "down" => down_container(&arguments[1..]),
-        _ => print_error("unknown command"),
+}
+
+/// Run `f` on the services resolved from the command line, or report why they could not be
+fn with_services(services: Result<Vec<String>, String>, f: impl FnOnce(&[String]) -> i32) -> i32 {
+    match services {
+        Ok(services) => f(&services),
+        Err(e) => {
+            print_error(&e);
+            1
+        }
     }
-    return 0;
 }
 
-fn down_container(arguments: &[String])
-{
-    if arguments.is_empty() {
-        print_error("Error: 'up' command requires at least one container");
-        return;
+/// The services to stop, the dependents before their dependencies: only `names`
+/// (services and profiles), unless `with_deps` adds the services they depend on
+fn stop_order(config: &DkConfig, names: &[String], with_deps: bool) -> Result<Vec<String>, String> {
+    let targets = config.expand(names)?;
+    let mut order = config.start_order(&targets)?;
+    order.reverse();
+    if !with_deps {
+        order.retain(|name| targets.contains(name));
     }
-    let config=get_config();
-    for arg in arguments {
-        let mut found = false;
-        for c in &config.ots {
-            if c.name == *arg {
-                found = true;
-                let name = format!("ots_{}", c.name);
-                print_info(&format!("Stopping and removing container {}", name));
-                print_and_run(&["docker", "rm", "-f", &name]);
+    Ok(order)
+}
+
+/// The typed definition of the service `name`, its variables replaced
+fn find_ots(config: &DkConfig, name: &str) -> Result<Ots, String> {
+    config.service(name).map_err(|e| match DkConfig::get_config_dir() {
+        // une variable absente est le plus souvent un secret pas encore déclaré
+        Ok(dir) if e.contains(" is not set ") => {
+            format!("{}, set it in {} (chmod 600)", e, dir.join(SECRETS_FILE_NAME).display())
+        }
+        _ => e,
+    })
+}
+
+/// Run `docker <action...> <container>` on the container of each service
+fn container_command(
+    executor: &dyn CommandExecutor,
+    config: &DkConfig,
+    names: &[String],
+    action: &[&str],
+    message: &str,
+) -> i32 {
+    let mut retcode = 0;
+    for name in names {
+        match find_ots(config, name) {
+            Ok(ots) => {
+                let container = ots.container_name();
+                print_info(&format!("{} container {}", message, container));
+                let mut cmd = vec!["docker"];
+                cmd.extend_from_slice(action);
+                cmd.push(&container);
+                if print_and_run(executor, &cmd) != 0 {
+                    retcode = 1;
+                }
+            }
+            Err(e) => {
+                print_error(&e);
+                retcode = 1;
             }
         }
-        if !found {
-            print_error(&format!("Container {} not found", arg));
+    }
+    retcode
+}
+
+fn shell(executor: &dyn CommandExecutor, config: &DkConfig, name: &str) -> i32 {
+    match find_ots(config, name) {
+        Ok(ots) => container_helper::exec_shell(executor, &ots.container_name()),
+        Err(e) => {
+            print_error(&e);
+            1
+        }
+    }
+}
+
+fn upgrade(executor: &dyn CommandExecutor, config: &DkConfig, names: &[String], all: bool) -> i32 {
+    let names: Vec<String> = if all {
+        config.ots.iter().map(|o| o.name.clone()).collect()
+    } else {
+        names.to_vec()
+    };
+    let mut retcode = 0;
+    for name in &names {
+        if let Err(e) = upgrade_one(executor, config, name, all) {
+            print_error(&e);
+            retcode = 1;
         }
     }
+    retcode
 }
 
-fn up_container(arguments: &[String]) {
-    if arguments.is_empty() {
-        print_error("Error: 'up' command requires at least one container");
-        return;
+/// Pull the image of a service and recreate its container when the image changed
+fn upgrade_one(executor: &dyn CommandExecutor, config: &DkConfig, name: &str, skip_missing: bool) -> Result<(), String> {
+    let ots = find_ots(config, name)?;
+    // une définition invalide arrête tout avant de supprimer le conteneur
+    let args = ots.docker_args()?;
+    let container = ots.container_name();
+    let old = match executor.execute(&["docker", "inspect", "--format", "{{.Image}}", &container]) {
+        Ok(id) => id.trim().to_string(),
+        Err(_) if skip_missing => {
+            print_info(&format!("{}: no container {}, skipped", name, container));
+            return Ok(());
+        }
+        Err(_) => return Err(format!("Container {} not found, create it with: dk ots up {}", container, name)),
+    };
+
+    print_info(&format!("Upgrading {} ({})", name, ots.image));
+    if print_and_run(executor, &["docker", "pull", &ots.image]) != 0 {
+        return Err(format!("Unable to pull {}", ots.image));
+    }
+    let new = capture(executor, &["docker", "image", "inspect", "--format", "{{.Id}}", &ots.image])
+        .map(|id| id.trim().to_string())
+        .map_err(|e| format!("Unable to inspect the image {} : {}", ots.image, e))?;
+    print_colored(&format!("(y) {} (w): (b){} (w)-> (b){}", name, old, new));
+    if old == new {
+        print_info(&format!("{} is up to date", name));
+        return Ok(());
+    }
+
+    let volumes = ots.named_volumes();
+    if !volumes.is_empty() {
+        print_info(&format!("Keeping the volumes {}", volumes.join(", ")));
+    }
+    // docker rm sans -v : les volumes nommés sont conservés
+    if print_and_run(executor, &["docker", "rm", "-f", &container]) != 0 {
+        return Err(format!("Unable to remove the container {}", container));
     }
-    let config=get_config();
+    let cmd: Vec<&str> = args.iter().map(|a| a.as_str()).collect();
+    if print_and_run(executor, &cmd) != 0 {
+        return Err(format!("Unable to recreate the container {}, the service is down", container));
+    }
+    Ok(())
+}
+
+/// Create the container of the services, in the given order.
+///
+/// A service whose container exists is started if needed. With `wait`, each
+/// service must be ready before the next one is started.
+fn up_container(executor: &dyn CommandExecutor, config: &DkConfig, arguments: &[String], wait: Option<Duration>) -> i32 {
+    let deadline = wait.map(|timeout| Instant::now() + timeout);
+    let containers = match get_containers(executor) {
+        Ok(containers) => containers,
+        Err(e) => {
+            print_error(&e);
+            return 1;
+        }
+    };
+    let mut retcode = 0;
     for arg in arguments {
-        let mut found = false;
-        for c in &config.ots {
-            if c.name == *arg {
-                found = true;
-                print_info(&format!("Starting container {}", c.name));
-                let cmd: Vec<&str> = c.command_line.split_whitespace().collect();
-                print_and_run(&cmd);
+        let (ots, args) = match find_ots(config, arg).and_then(|ots| ots.docker_args().map(|args| (ots, args))) {
+            Ok(found) => found,
+            Err(e) => {
+                print_error(&e);
+                retcode = 1;
+                continue;
+            }
+        };
+        let name = ots.container_name();
+        let code = match containers.iter().find(|c| c.name == name) {
+            Some(c) if c.state == "running" => {
+                print_info(&format!("Container {} is already running", name));
+                0
+            }
+            Some(_) => {
+                print_info(&format!("Starting the existing container {}", name));
+                print_and_run(executor, &["docker", "start", &name])
             }
+            None => {
+                print_info(&format!("Starting container {}", arg));
+                let cmd: Vec<&str> = args.iter().map(|a| a.as_str()).collect();
+                print_and_run(executor, &cmd)
+            }
+        };
+        if code != 0 {
+            retcode = 1;
+            continue;
         }
-        if !found {
-            print_error(&format!("Container {} not found", arg));
+        if let Some(deadline) = deadline {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if readiness::wait_ready(executor, std::slice::from_ref(&ots), remaining) != 0 {
+                // les services suivants peuvent dépendre de celui-ci
+                return 1;
+            }
         }
     }
+    retcode
 }
 
 
 
-fn get_config() -> DkConfig {
-    // Charger la configuration depuis le fichier
+/// Add the services of a compose file to the configuration and save it
+fn import(file: &str) -> i32 {
+    let yaml = match fs::read_to_string(file) {
+        Ok(yaml) => yaml,
+        Err(e) => {
+            print_error(&format!("Unable to read {} : {}", file, e));
+            return 1;
+        }
+    };
+    // les chemins relatifs du fichier compose partent de son répertoire
+    let dir = Path::new(file).parent().filter(|d| !d.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let dir = fs::canonicalize(dir).unwrap_or_else(|_| dir.to_path_buf());
+    update_config(|config| {
+        let added = add_services(config, &yaml, &dir)?;
+        Ok(format!("Imported {}", added.join(", ")))
+    })
+}
+
+/// Read the configuration file again for a change: an invalid file is
+/// reported, never replaced by the defaults
+fn load_for_update() -> Result<DkConfig, String> {
     match DkConfig::load_from_file() {
-        Ok(config) => {
-            config
+        Ok(config) => Ok(config),
+        Err(e) if e.is_missing() => Ok(DkConfig::default_config()),
+        Err(e) => Err(format!("Invalid configuration {} (fix it with dk ots edit)", e)),
+    }
+}
+
+/// Services of the project file that a change of the global file from `before` to `after` touches
+fn project_conflicts(layered: &LayeredConfig, before: &DkConfig, after: &DkConfig) -> Vec<String> {
+    let find = |config: &DkConfig, name: &str| config.ots.iter().find(|o| o.name == name).cloned();
+    layered
+        .project_services()
+        .into_iter()
+        .filter(|name| find(before, name) != find(after, name))
+        .collect()
+}
+
+/// Apply `change` to the configuration file and save it, `change` gives the message to print.
+///
+/// Only the global file is written: a change of a service set by the project
+/// file is refused, the project would hide it.
+fn update_config(change: impl FnOnce(&mut DkConfig) -> Result<String, String>) -> i32 {
+    let mut config = match load_for_update() {
+        Ok(config) => config,
+        Err(e) => {
+            print_error(&e);
+            return 1;
+        }
+    };
+    let before = config.clone();
+    let message = match change(&mut config) {
+        Ok(message) => message,
+        Err(e) => {
+            print_error(&e);
+            return 1;
+        }
+    };
+    if let Ok(layered) = config_layers::load_config()
+        && let Some(project) = &layered.project
+    {
+        let conflicts = project_conflicts(&layered, &before, &config);
+        if !conflicts.is_empty() {
+            print_error(&format!(
+                "{} set in the project file {}, edit that file (dk ots only changes the global file)",
+                conflicts.join(", "),
+                project.display()
+            ));
+            return 1;
         }
-        Err(_e) => {
-            DkConfig::create_default()
+    }
+    for problem in config.check() {
+        print_warning(&format!("Configuration: {}", problem));
+    }
+    if let Err(e) = config.save_to_file() {
+        print_error(&format!("Unable to save the configuration : {}", e));
+        return 1;
+    }
+    print_info(&message);
+    0
+}
+
+fn add(executor: &dyn CommandExecutor, args: Box<OtsAddArgs>) -> i32 {
+    let name = args.name.clone();
+    let ots = match &args.from {
+        Some(container) => ots_from_container(executor, &name, container),
+        None => Ok(Ots { name: name.clone(), ..Default::default() }),
+    };
+    match ots.and_then(|ots| apply_args(ots, *args)) {
+        Ok(ots) => update_config(|config| {
+            config.add(ots)?;
+            Ok(format!("Added {} (dk ots up {})", name, name))
+        }),
+        Err(e) => {
+            print_error(&e);
+            1
         }
     }
 }
 
+/// Complete a service with the options of `dk ots add`
+fn apply_args(mut ots: Ots, args: OtsAddArgs) -> Result<Ots, String> {
+    if let Some(image) = args.image {
+        ots.image = image;
+    }
+    if args.container_name.is_some() {
+        ots.container_name = args.container_name;
+    }
+    for variable in &args.env {
+        let (key, value) = variable
+            .split_once('=')
+            .ok_or_else(|| format!("Invalid environment variable '{}' (KEY=value)", variable))?;
+        ots.env.insert(key.to_string(), value.to_string());
+    }
+    for port in &args.ports {
+        parse_port(port)?;
+    }
+    ots.ports.extend(args.ports);
+    ots.volumes.extend(args.volumes);
+    ots.mounts.extend(args.mounts);
+    if args.restart.is_some() {
+        ots.restart = args.restart;
+    }
+    ots.depends_on.extend(args.depends_on);
+    if !args.command.is_empty() {
+        ots.command = args.command;
+    }
+    Ok(ots)
+}
+
+/// Definition of a service copied from an existing container.
+///
+/// The environment and the command inherited from the image are left out.
+fn ots_from_container(executor: &dyn CommandExecutor, name: &str, container: &str) -> Result<Ots, String> {
+    let output = executor
+        .execute(&["docker", "inspect", "--type", "container", container])
+        .map_err(|_| format!("Container {} not found", container))?;
+    let inspect: Value = serde_json::from_str(&output).map_err(|e| format!("Invalid docker inspect output : {}", e))?;
+    let c = &inspect[0];
+    let image = c["Config"]["Image"].as_str().unwrap_or_default().to_string();
+    let image_config = executor
+        .execute(&["docker", "image", "inspect", &image])
+        .ok()
+        .and_then(|output| serde_json::from_str::<Value>(&output).ok())
+        .map(|i| i[0]["Config"].clone())
+        .unwrap_or_default();
+    let texts = |value: &Value| -> Vec<String> {
+        value
+            .as_array()
+            .map(|a| a.iter().filter_map(|v| v.as_str()).map(String::from).collect())
+            .unwrap_or_default()
+    };
+
+    let mut ots = Ots { name: name.to_string(), image, ..Default::default() };
+    let container_name = c["Name"].as_str().unwrap_or(container).trim_start_matches('/');
+    if container_name != ots.container_name() {
+        ots.container_name = Some(container_name.to_string());
+    }
+    let image_env = texts(&image_config["Env"]);
+    for variable in texts(&c["Config"]["Env"]).iter().filter(|v| !image_env.contains(v)) {
+        if let Some((key, value)) = variable.split_once('=') {
+            ots.env.insert(key.to_string(), value.to_string());
+        }
+    }
+    if let Some(bindings) = c["HostConfig"]["PortBindings"].as_object() {
+        for (port, hosts) in bindings {
+            let port = port.strip_suffix("/tcp").unwrap_or(port);
+            for host in hosts.as_array().into_iter().flatten() {
+                let ip = host["HostIp"].as_str().unwrap_or_default();
+                let host_port = host["HostPort"].as_str().unwrap_or_default();
+                ots.ports.push(match (ip, host_port) {
+                    (_, "") => port.to_string(),
+                    ("" | "0.0.0.0" | "::", _) => format!("{}:{}", host_port, port),
+                    _ => format!("{}:{}:{}", ip, host_port, port),
+                });
+            }
+        }
+    }
+    ots.volumes = texts(&c["HostConfig"]["Binds"]);
+    for mount in c["HostConfig"]["Mounts"].as_array().into_iter().flatten() {
+        let mut spec = format!(
+            "type={},src={},dst={}",
+            mount["Type"].as_str().unwrap_or("volume"),
+            mount["Source"].as_str().unwrap_or_default(),
+            mount["Target"].as_str().unwrap_or_default()
+        );
+        if mount["ReadOnly"].as_bool() == Some(true) {
+            spec.push_str(",readonly");
+        }
+        ots.mounts.push(spec);
+    }
+    let policy = &c["HostConfig"]["RestartPolicy"];
+    ots.restart = match (policy["Name"].as_str().unwrap_or_default(), policy["MaximumRetryCount"].as_u64().unwrap_or(0)) {
+        ("" | "no", _) => None,
+        ("on-failure", retries) if retries > 0 => Some(format!("on-failure:{}", retries)),
+        (name, _) => Some(name.to_string()),
+    };
+    let command = texts(&c["Config"]["Cmd"]);
+    if command != texts(&image_config["Cmd"]) {
+        ots.command = command;
+    }
+    Ok(ots)
+}
+
+/// Rename a service, its container is renamed too when its name comes from the service
+fn rename(executor: &dyn CommandExecutor, config: &DkConfig, old: &str, new: &str) -> i32 {
+    let follows = config
+        .ots
+        .iter()
+        .find(|o| o.name == old)
+        .is_some_and(|o| o.container_name.is_none() && !o.is_legacy());
+    let retcode = update_config(|config| {
+        config.rename(old, new)?;
+        Ok(format!("Renamed {} to {}", old, new))
+    });
+    let old_container = format!("ots_{}", old);
+    if retcode == 0 && follows && get_containers(executor).is_ok_and(|cs| cs.iter().any(|c| c.name == old_container)) {
+        return print_and_run(executor, &["docker", "rename", &old_container, &format!("ots_{}", new)]);
+    }
+    retcode
+}
+
+/// Edit the configuration file with `$VISUAL` or `$EDITOR`.
+///
+/// The changes are made on a copy, saved only once it parses; the problems
+/// found by the check are shown before saving.
+pub fn edit(executor: &dyn CommandExecutor) -> i32 {
+    let path = match DkConfig::get_config_path() {
+        Ok(path) => path,
+        Err(e) => {
+            print_error(&e.to_string());
+            return 1;
+        }
+    };
+    if let Ok(layered) = config_layers::load_config()
+        && let Some(project) = &layered.project
+    {
+        print_warning(&format!("{} is read after {} and overrides it", project.display(), path.display()));
+    }
+    let format = ConfigFormat::from_path(&path);
+    let original = match fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            format.serialize(&DkConfig::default_config()).unwrap_or_default()
+        }
+        Err(e) => {
+            print_error(&format!("Unable to read {} : {}", path.display(), e));
+            return 1;
+        }
+    };
+    let editor = env::var("VISUAL").or_else(|_| env::var("EDITOR")).unwrap_or_else(|_| String::from("vi"));
+    let mut editor = match split_shell_words(&editor) {
+        Ok(words) if !words.is_empty() => words,
+        _ => {
+            print_error(&format!("Invalid editor '{}'", editor));
+            return 1;
+        }
+    };
+    // le brouillon garde l'extension pour la coloration syntaxique de l'éditeur
+    let stem = path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+    let draft = path.with_file_name(format!("{}.edit.{}", stem, format.extension()));
+    editor.push(draft.display().to_string());
+    let editor: Vec<&str> = editor.iter().map(|w| w.as_str()).collect();
+
+    let mut text = original.clone();
+    let retcode = loop {
+        if let Err(e) = fs::write(&draft, &text) {
+            print_error(&format!("Unable to write {} : {}", draft.display(), e));
+            break 1;
+        }
+        match executor.run(&editor) {
+            Ok(0) => {}
+            Ok(code) => {
+                print_error(&format!("The editor exited with code {}, changes discarded", code));
+                break 1;
+            }
+            Err(e) => {
+                print_error(&e);
+                break 1;
+            }
+        }
+        text = fs::read_to_string(&draft).unwrap_or_default();
+        if text == original {
+            print_info("No change");
+            break 0;
+        }
+        let config = match DkConfig::read_file(&draft) {
+            Ok(config) => config,
+            Err(e) => {
+                print_error(&format!("Invalid configuration {}", e));
+                if confirm("Edit again?", true) {
+                    continue;
+                }
+                print_info("Changes discarded");
+                break 1;
+            }
+        };
+        let problems = config.check();
+        for problem in &problems {
+            print_warning(&format!("Configuration: {}", problem));
+        }
+        if !problems.is_empty() && confirm("Edit again?", true) {
+            continue;
+        }
+        break match config.save_to_file() {
+            Ok(()) => {
+                print_info(&format!("Configuration saved ({} services)", config.ots.len()));
+                0
+            }
+            Err(e) => {
+                print_error(&format!("Unable to save the configuration : {}", e));
+                1
+            }
+        };
+    };
+    let _ = fs::remove_file(&draft);
+    retcode
+}
+
+/// Add the services of a compose file, returns their names
+fn add_services(config: &mut DkConfig, yaml: &str, dir: &Path) -> Result<Vec<String>, String> {
+    let (entries, warnings) = compose::import(yaml, dir)?;
+    for warning in &warnings {
+        print_warning(warning);
+    }
+    if let Some(existing) = entries.iter().find(|e| config.ots.iter().any(|o| o.name == e.name)) {
+        return Err(format!("OTS {} already exists", existing.name));
+    }
+    let names = entries.iter().map(|e| e.name.clone()).collect();
+    config.ots.extend(entries);
+    Ok(names)
+}
+
+fn export(config: &DkConfig, names: &[String]) -> i32 {
+    match compose::export(config, names) {
+        Ok((yaml, warnings)) => {
+            for warning in &warnings {
+                print_warning(warning);
+            }
+            print!("{}", yaml);
+            0
+        }
+        Err(e) => {
+            print_error(&e);
+            1
+        }
+    }
+}
+
+/// Print the definition of a service as used by dk, the secrets being masked
+fn show_service(config: &DkConfig, name: &str) -> i32 {
+    let ots = match config.masked_service(name) {
+        Ok(ots) => ots,
+        Err(e) => {
+            print_error(&e);
+            return 1;
+        }
+    };
+    println!("{}", serde_json::to_string_pretty(&ots).unwrap_or_default());
+    if let Ok(args) = ots.docker_args() {
+        let args: Vec<String> = args
+            .iter()
+            .map(|a| if a.contains([' ', '\'', '"']) { format!("'{}'", a.replace('\'', "'\\''")) } else { a.clone() })
+            .collect();
+        print_colored(&format!("(y)Command (w): {}", args.join(" ")));
+    }
+    0
+}
+
+/// Report the problems of the configuration, 1 when there are some
+fn check(config: &DkConfig) -> i32 {
+    let problems = config.check();
+    if problems.is_empty() {
+        print_info(&format!("The configuration is valid ({} services)", config.ots.len()));
+        return 0;
+    }
+    for problem in &problems {
+        print_error(&problem.to_string());
+    }
+    print_error(&format!("{} problem(s) found in the configuration", problems.len()));
+    1
+}
+
+/// State of every service of the configuration
+pub fn get_status(executor: &dyn CommandExecutor, config: &DkConfig) -> Result<Vec<OtsStatus>, String> {
+    let containers = get_containers(executor)?;
+    let services = config
+        .ots
+        .iter()
+        .map(|o| {
+            // une entrée invalide est listée quand même, avec son erreur
+            let ots = config.masked_service(&o.name).unwrap_or_else(|e| {
+                print_warning(&e);
+                o.clone()
+            });
+            let port = ots.host_port();
+            let name = ots.container_name();
+            let container = containers.iter().find(|c| c.name == name);
+            let (uptime, health) = container.map(parse_status).unwrap_or_default();
+            OtsStatus {
+                name: ots.name.clone(),
+                image: ots.image.clone(),
+                port,
+                exists: container.is_some(),
+                state: container.map(|c| c.state.clone()).unwrap_or_default(),
+                container: name,
+                health,
+                uptime,
+                url: ots.status_url().unwrap_or_default(),
+            }
+        })
+        .collect();
+    Ok(services)
+}
+
+/// Uptime and health of a container, from its status (`Up 3 hours (healthy)`)
+fn parse_status(container: &Container) -> (String, String) {
+    let Some(rest) = container.status.strip_prefix("Up ") else {
+        return (String::new(), String::new());
+    };
+    match rest.split_once(" (") {
+        Some((uptime, health)) => {
+            let health = health.trim_end_matches(')');
+            (uptime.to_string(), health.strip_prefix("health: ").unwrap_or(health).to_string())
+        }
+        None => (rest.to_string(), String::new()),
+    }
+}
+
+fn show(executor: &dyn CommandExecutor, config: &DkConfig, format: OutputFormat) -> i32 {
+    let services = match get_status(executor, config) {
+        Ok(services) => services,
+        Err(e) => {
+            eprint_error(&e);
+            return 1;
+        }
+    };
+    if let Some(out) = render(&services, format) {
+        print!("{}", out);
+        return 0;
+    }
+    let mut table = new_table(&["Index", "Name", "Image", "Port", "Container", "State", "Uptime", "URL"]);
+
+    for (index, s) in services.iter().enumerate() {
+        let state = match (s.exists, s.health.is_empty()) {
+            (false, _) => String::from("not created"),
+            (true, true) => s.state.clone(),
+            (true, false) => format!("{} ({})", s.state, s.health),
+        };
+        table.add_row(Row::new(vec![
+            Cell::new(&(index + 1).to_string()),
+            Cell::new(&s.name).with_style(Attr::Bold),
+            Cell::new(&s.image),
+            Cell::new(&s.port.map(|p| p.to_string()).unwrap_or_default()),
+            Cell::new(&s.container),
+            Cell::new(&state),
+            Cell::new(&s.uptime),
+            Cell::new(&s.url),
+        ]));
+    }
+
+    print_table(&table);
+    0
+}
+
+pub fn get_config() -> Result<DkConfig, String> {
+    // fichier global, fichier du projet puis variables DK_*
+    let mut config = config_layers::load_config()?.config;
+    // variables des services : .env et secrets.env à côté de la configuration
+    if let Ok(dir) = DkConfig::get_config_dir() {
+        match Variables::load(&dir) {
+            Ok(variables) => config.variables = variables,
+            Err(e) => print_error(&e),
+        }
+    }
+    Ok(config)
+}
+
 
 
 //             "glances" => {
@@ -183,3 +874,328 @@ fn get_config() -> DkConfig {
 //     }
 // }
 
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The default configuration, with the secret it needs
+    fn config() -> DkConfig {
+        let mut config = DkConfig::default_config();
+        config.variables = Variables::with_values(
+            Default::default(),
+            std::collections::BTreeMap::from([(String::from("SA_PASSWORD"), String::from("Sh@dokN0tD€ad!"))]),
+        );
+        config
+    }
+
+    #[test]
+    fn test_up() {
+        let mut mock = MockCommandExecutor::new();
+        mock.expect(&["docker", "ps", "..."], MockResponse::ok(""))
+            .expect(&["docker", "run", "..."], MockResponse::ok("0123456789ab\n"));
+        let command = OtsCommand::Up { names: vec![String::from("kroki")], wait: false, timeout: Duration::from_secs(60) };
+
+        assert_eq!(cmd(&mock, &config(), Some(command)), 0);
+        assert_eq!(
+            mock.command_lines()[1..],
+            ["docker run -d --name ots_kroki -p 25100:8000 --restart unless-stopped yuzutech/kroki"]
+        );
+    }
+
+    #[test]
+    fn test_up_existing_containers() {
+        let mut mock = MockCommandExecutor::new();
+        mock.expect(&["docker", "ps", "..."], MockResponse::fixture("docker_ps.txt"))
+            .expect(&["docker", "start", "*"], MockResponse::ok(""));
+        let names = vec![String::from("kroki"), String::from("sqlserver")];
+        let command = OtsCommand::Up { names, wait: false, timeout: Duration::ZERO };
+
+        assert_eq!(cmd(&mock, &config(), Some(command)), 0);
+        assert_eq!(mock.command_lines()[1..], ["docker start ots_sqlserver"]);
+    }
+
+    #[test]
+    fn test_dependencies_and_profiles() {
+        let mut mock = MockCommandExecutor::new();
+        mock.expect(&["docker", "ps", "..."], MockResponse::ok(""))
+            .expect(&["docker", "run", "..."], MockResponse::ok(""))
+            .expect(&["docker", "rm", "-f", "*"], MockResponse::ok(""));
+        let mut config = config();
+        config.ots[0].depends_on = vec![String::from("sqlserver"), String::from("kroki")];
+        config.ots[1].depends_on = vec![String::from("kroki")];
+
+        let command = OtsCommand::Up { names: vec![String::from("portainer")], wait: false, timeout: Duration::ZERO };
+        assert_eq!(cmd(&mock, &config, Some(command)), 0);
+        let down = |names: &[&str], with_deps: bool| OtsCommand::Down { names: names.iter().map(|n| n.to_string()).collect(), with_deps };
+        assert_eq!(cmd(&mock, &config, Some(down(&["@monitoring"], false))), 0);
+        assert_eq!(cmd(&mock, &config, Some(down(&["portainer"], false))), 0);
+        assert_eq!(cmd(&mock, &config, Some(down(&["kroki", "portainer"], false))), 0);
+        assert_eq!(cmd(&mock, &config, Some(down(&["portainer"], true))), 0);
+        let containers: Vec<String> = mock
+            .calls()
+            .iter()
+            .filter(|c| c[1] != "ps")
+            .map(|c| if c[1] == "run" { c[4].clone() } else { c[3].clone() })
+            .collect();
+        assert_eq!(
+            containers,
+            vec![
+                "ots_kroki", "ots_sqlserver", "ots_portainer",
+                "ots_ctop", "ots_doku", "ots_dozzle",
+                "ots_portainer",
+                "ots_portainer", "ots_kroki",
+                "ots_portainer", "ots_sqlserver", "ots_kroki",
+            ]
+        );
+
+        config.ots[2].depends_on = vec![String::from("portainer")];
+        let command = OtsCommand::Up { names: vec![String::from("kroki")], wait: false, timeout: Duration::ZERO };
+        assert_eq!(cmd(&mock, &config, Some(command)), 1);
+        assert_eq!(mock.calls().len(), 13);
+    }
+
+    #[test]
+    fn test_down() {
+        let mut mock = MockCommandExecutor::new();
+        mock.expect(&["docker", "rm", "-f", "*"], MockResponse::ok(""));
+        let command = OtsCommand::Down { names: vec![String::from("kroki")], with_deps: false };
+
+        assert_eq!(cmd(&mock, &config(), Some(command)), 0);
+        assert_eq!(mock.command_lines(), vec!["docker rm -f ots_kroki"]);
+    }
+
+    #[test]
+    fn test_lifecycle() {
+        let mut mock = MockCommandExecutor::new();
+        mock.expect(&["docker", "..."], MockResponse::ok(""));
+        let config = config();
+        let names = |names: &[&str]| names.iter().map(|n| n.to_string()).collect::<Vec<String>>();
+
+        assert_eq!(cmd(&mock, &config, Some(OtsCommand::Stop { names: names(&["kroki", "dozzle"]), with_deps: false })), 0);
+        assert_eq!(cmd(&mock, &config, Some(OtsCommand::Start { names: names(&["kroki"]) })), 0);
+        assert_eq!(cmd(&mock, &config, Some(OtsCommand::Restart { names: names(&["kroki"]) })), 0);
+        assert_eq!(cmd(&mock, &config, Some(OtsCommand::Logs { name: String::from("kroki"), tail: 20 })), 0);
+        assert_eq!(cmd(&mock, &config, Some(OtsCommand::Shell { name: String::from("kroki") })), 0);
+        assert_eq!(cmd(&mock, &config, Some(OtsCommand::Stop { names: names(&["unknown"]), with_deps: false })), 1);
+        assert_eq!(
+            mock.command_lines(),
+            vec![
+                "docker stop ots_dozzle",
+                "docker stop ots_kroki",
+                "docker start ots_kroki",
+                "docker restart ots_kroki",
+                "docker logs -f --tail 20 ots_kroki",
+                "docker exec -it ots_kroki /bin/bash",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_upgrade() {
+        let mut mock = MockCommandExecutor::new();
+        mock.expect(&["docker", "inspect", "*", "*", "ots_portainer"], MockResponse::ok("sha256:0f1c5a3b\n"))
+            .expect(&["docker", "inspect", "..."], MockResponse::fail(1, "No such object"))
+            .expect(&["docker", "pull", "*"], MockResponse::ok(""))
+            .expect(&["docker", "image", "inspect", "..."], MockResponse::ok("sha256:5e7a21c4\n"))
+            .expect(&["docker", "rm", "-f", "*"], MockResponse::ok(""))
+            .expect(&["docker", "run", "..."], MockResponse::ok(""));
+
+        let command = OtsCommand::Upgrade { all: true, names: vec![] };
+        assert_eq!(cmd(&mock, &config(), Some(command)), 0);
+        let lines = mock.command_lines();
+        let actions: Vec<&String> = lines.iter().filter(|l| !l.starts_with("docker inspect")).collect();
+        assert_eq!(
+            actions[..3],
+            [
+                "docker pull portainer/portainer-ce:latest",
+                "docker image inspect --format {{.Id}} portainer/portainer-ce:latest",
+                "docker rm -f ots_portainer",
+            ]
+        );
+        assert!(actions[3].starts_with("docker run -d --name ots_portainer"));
+        assert_eq!(actions.len(), 4);
+
+        let command = OtsCommand::Upgrade { all: false, names: vec![String::from("kroki")] };
+        assert_eq!(cmd(&mock, &config(), Some(command)), 1);
+
+        // sans argv valide, le conteneur n'est ni supprimé ni recréé
+        let calls = mock.calls().len();
+        let mut config = config();
+        config.ots.iter_mut().find(|o| o.name == "portainer").unwrap().image.clear();
+        let command = OtsCommand::Upgrade { all: false, names: vec![String::from("portainer")] };
+        assert_eq!(cmd(&mock, &config, Some(command)), 1);
+        assert_eq!(mock.calls().len(), calls);
+    }
+
+    #[test]
+    fn test_upgrade_up_to_date() {
+        let mut mock = MockCommandExecutor::new();
+        mock.expect(&["docker", "inspect", "..."], MockResponse::ok("sha256:7b2e4c9a\n"))
+            .expect(&["docker", "pull", "*"], MockResponse::ok(""))
+            .expect(&["docker", "image", "inspect", "..."], MockResponse::ok("sha256:7b2e4c9a\n"));
+
+        let command = OtsCommand::Upgrade { all: false, names: vec![String::from("kroki")] };
+        assert_eq!(cmd(&mock, &config(), Some(command)), 0);
+        assert_eq!(mock.calls().len(), 3);
+    }
+
+    #[test]
+    fn test_up_wait() {
+        let mut mock = MockCommandExecutor::new();
+        mock.expect(&["docker", "ps", "..."], MockResponse::ok(""))
+            .expect(&["docker", "run", "..."], MockResponse::ok("0123456789ab\n"))
+            .expect(&["docker", "inspect", "..."], MockResponse::ok("exited|\n"));
+        let command = OtsCommand::Up { names: vec![String::from("ctop")], wait: true, timeout: Duration::from_secs(5) };
+
+        assert_eq!(cmd(&mock, &config(), Some(command)), 1);
+        assert_eq!(mock.calls().len(), 3);
+    }
+
+    #[test]
+    fn test_show() {
+        let mock = MockCommandExecutor::new();
+        // sans SA_PASSWORD, le service s'affiche quand même
+        let defaults = DkConfig::default_config();
+        assert_eq!(cmd(&mock, &defaults, Some(OtsCommand::Show { name: String::from("sqlserver") })), 0);
+        assert_eq!(defaults.masked_service("sqlserver").unwrap().env["SA_PASSWORD"], "<unset>");
+        assert!(find_ots(&defaults, "sqlserver").unwrap_err().contains("SA_PASSWORD is not set"));
+        let config = config();
+        assert_eq!(cmd(&mock, &config, Some(OtsCommand::Show { name: String::from("sqlserver") })), 0);
+        assert_eq!(config.masked_service("sqlserver").unwrap().env["SA_PASSWORD"], "******");
+        assert!(config.service("sqlserver").unwrap().docker_args().unwrap().contains(&String::from("SA_PASSWORD=Sh@dokN0tD€ad!")));
+    }
+
+    #[test]
+    fn test_add_services() {
+        let mut config = config();
+        let yaml = "services:\n  grafana:\n    image: grafana/grafana\n    ports: [\"3000:3000\"]\n";
+        assert_eq!(add_services(&mut config, yaml, Path::new("/")), Ok(vec![String::from("grafana")]));
+        assert_eq!(config.ots[8].host_port(), Some(3000));
+        assert_eq!(add_services(&mut config, yaml, Path::new("/")), Err(String::from("OTS grafana already exists")));
+        assert_eq!(config.ots.len(), 9);
+    }
+
+    #[test]
+    fn test_project_conflicts() {
+        let layered = LayeredConfig {
+            origins: [("ots.kroki.ports", "/work/.dk/dk_config.yaml"), ("ots.ctop.image", "/home/.dk/dk_config.json")]
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            project: Some(std::path::PathBuf::from("/work/.dk/dk_config.yaml")),
+            ..Default::default()
+        };
+        let before = config();
+        let mut after = before.clone();
+        after.remove(&[String::from("ctop")]).unwrap();
+        assert!(project_conflicts(&layered, &before, &after).is_empty());
+        after.rename("kroki", "diagrams").unwrap();
+        assert_eq!(project_conflicts(&layered, &before, &after), vec!["kroki"]);
+    }
+
+    #[test]
+    fn test_add_from_container() {
+        let mut mock = MockCommandExecutor::new();
+        mock.expect(&["docker", "inspect", "--type", "container", "web"], MockResponse::fixture("docker_inspect_container.json"))
+            .expect(&["docker", "image", "inspect", "nginx:1.27"], MockResponse::fixture("docker_image_inspect.json"))
+            .expect(&["docker", "inspect", "..."], MockResponse::fail(1, "Error: No such container: nothing"));
+        let ots = ots_from_container(&mock, "web", "web").unwrap();
+        assert_eq!(ots.image, "nginx:1.27");
+        assert_eq!(ots.container_name.as_deref(), Some("web"));
+        assert_eq!(ots.ports, vec!["127.0.0.1:8443:443", "8080:80"]);
+        assert_eq!(ots.env, std::collections::BTreeMap::from([(String::from("SERVER_NAME"), String::from("example.org"))]));
+        assert_eq!(ots.volumes, vec!["/srv/www:/usr/share/nginx/html:ro"]);
+        assert_eq!(ots.mounts, vec!["type=volume,src=web_cache,dst=/var/cache/nginx"]);
+        assert_eq!(ots.restart.as_deref(), Some("unless-stopped"));
+        assert!(ots.command.is_empty());
+        assert_eq!(ots_from_container(&mock, "web", "nothing"), Err(String::from("Container nothing not found")));
+
+        let args = OtsAddArgs { name: String::from("web"), env: vec![String::from("TZ=UTC")], restart: Some(String::from("always")), ..Default::default() };
+        let ots = apply_args(ots, args).unwrap();
+        assert_eq!(ots.env["TZ"], "UTC");
+        assert_eq!(ots.restart.as_deref(), Some("always"));
+    }
+
+    #[test]
+    fn test_add_from_options() {
+        let args = OtsAddArgs {
+            name: String::from("grafana"),
+            image: Some(String::from("grafana/grafana")),
+            ports: vec![String::from("3000:3000")],
+            command: vec![String::from("--debug")],
+            ..Default::default()
+        };
+        let ots = apply_args(Ots { name: String::from("grafana"), ..Default::default() }, args).unwrap();
+        assert_eq!(
+            ots.docker_args().unwrap().join(" "),
+            "docker run -d --name ots_grafana -p 3000:3000 grafana/grafana --debug"
+        );
+        let args = OtsAddArgs { env: vec![String::from("TZ")], ..Default::default() };
+        assert!(apply_args(Ots::default(), args).is_err());
+        let args = OtsAddArgs { ports: vec![String::from("http")], ..Default::default() };
+        assert!(apply_args(Ots::default(), args).is_err());
+    }
+
+    #[test]
+    fn test_check() {
+        let mock = MockCommandExecutor::new();
+        let mut config = config();
+        assert_eq!(cmd(&mock, &config, Some(OtsCommand::Check)), 0);
+        config.ots[7].container_name = Some(String::from("ots_doku"));
+        assert_eq!(cmd(&mock, &config, Some(OtsCommand::Check)), 1);
+    }
+
+    #[test]
+    fn test_status() {
+        let mut mock = MockCommandExecutor::new();
+        mock.expect(&["docker", "ps", "..."], MockResponse::fixture("docker_ps.txt"));
+
+        let status = get_status(&mock, &config()).unwrap();
+        let mut failing = MockCommandExecutor::new();
+        failing.expect(&["docker", "..."], MockResponse::fail(1, "Cannot connect to the Docker daemon"));
+        assert_eq!(get_status(&failing, &config()).unwrap_err(), "Cannot connect to the Docker daemon");
+        assert_eq!(cmd(&failing, &config(), Some(OtsCommand::Ls { output: OutputFormat::Json })), 1);
+        let kroki = status.iter().find(|s| s.name == "kroki").unwrap();
+        assert_eq!(kroki.port, Some(25100));
+        assert_eq!(kroki.state, "running");
+        assert_eq!(kroki.health, "healthy");
+        assert_eq!(kroki.uptime, "3 hours");
+        assert_eq!(kroki.url, "http://localhost:25100");
+        let sqlserver = status.iter().find(|s| s.name == "sqlserver").unwrap();
+        assert!(sqlserver.exists);
+        assert_eq!((sqlserver.state.as_str(), sqlserver.uptime.as_str()), ("exited", ""));
+        // 1433 n'est pas un port web : pas d'URL
+        assert_eq!((sqlserver.port, sqlserver.url.as_str()), (Some(1433), ""));
+        let rabbitmq = status.iter().find(|s| s.name == "rabbitmq").unwrap();
+        assert_eq!(rabbitmq.url, "http://localhost:15672");
+        let excalidraw = status.iter().find(|s| s.name == "excalidraw").unwrap();
+        assert!(!excalidraw.exists);
+        assert_eq!(excalidraw.url, "http://localhost:25000");
+        assert_eq!(mock.calls().len(), 1);
+    }
+
+    #[test]
+    fn test_up_legacy_command_line() {
+        let mut mock = MockCommandExecutor::new();
+        mock.expect(&["docker", "ps", "..."], MockResponse::ok(""))
+            .expect(&["docker", "run", "..."], MockResponse::ok("0123456789ab\n"))
+            .expect(&["docker", "rm", "-f", "*"], MockResponse::ok(""));
+        let config = DkConfig {
+            ots: vec![Ots {
+                name: String::from("web"),
+                command_line: Some(String::from(
+                    "docker run -d --name my_web -e 'TITLE=My web' --restart unless-stopped nginx",
+                )),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+
+        assert_eq!(cmd(&mock, &config, Some(OtsCommand::Up { names: vec![String::from("web")], wait: false, timeout: Duration::ZERO })), 0);
+        assert_eq!(cmd(&mock, &config, Some(OtsCommand::Down { names: vec![String::from("web")], with_deps: false })), 0);
+        assert_eq!(mock.calls()[1][6], "TITLE=My web");
+        assert_eq!(mock.command_lines()[2], "docker rm -f my_web");
+    }
+}
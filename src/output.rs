@@ -0,0 +1,136 @@
+use clap::ValueEnum;
+use serde::Serialize;
+use serde_json::Value;
+
+/// Output format of the listings (`-o`, `--output`)
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum OutputFormat {
+    Table,
+    Json,
+    Csv,
+    Tsv,
+    #[value(alias = "yml")]
+    Yaml,
+}
+
+/// An item of a listing with the rank shown in the tables
+#[derive(Serialize)]
+struct Ranked<'a, T: Serialize> {
+    index: usize,
+    #[serde(flatten)]
+    item: &'a T,
+}
+
+/// Render a listing in a machine readable format.
+///
+/// Every record gets an `index` field holding the rank shown in the tables,
+/// so a rank seen by a human can be resolved by a script.
+/// Returns `None` for `OutputFormat::Table`, which is rendered by the caller.
+/// The CSV and TSV headers come from `T::default()`, so they are written even
+/// for an empty listing.
+pub fn render<T: Serialize + Default>(items: &[T], format: OutputFormat) -> Option<String> {
+    let ranked: Vec<Ranked<T>> = items
+        .iter()
+        .enumerate()
+        .map(|(i, item)| Ranked { index: i + 1, item })
+        .collect();
+    let empty = T::default();
+    let sample = Ranked { index: 0, item: &empty };
+    match format {
+        OutputFormat::Table => None,
+        OutputFormat::Json => Some(serde_json::to_string_pretty(&ranked).unwrap_or_default() + "\n"),
+        OutputFormat::Yaml => Some(serde_yaml::to_string(&ranked).unwrap_or_default()),
+        OutputFormat::Csv => Some(delimited(&ranked, &sample, ',')),
+        OutputFormat::Tsv => Some(delimited(&ranked, &sample, '\t')),
+    }
+}
+
+fn delimited<T: Serialize>(items: &[T], sample: &T, separator: char) -> String {
+    let object = |i: &T| match serde_json::to_value(i) {
+        Ok(Value::Object(map)) => Some(map),
+        _ => None,
+    };
+    let rows: Vec<serde_json::Map<String, Value>> = items.iter().filter_map(object).collect();
+    let Some(first) = rows.first().cloned().or_else(|| object(sample)) else {
+        return String::new();
+    };
+
+    let headers: Vec<&String> = first.keys().collect();
+    let mut out = String::new();
+    let line: Vec<String> = headers.iter().map(|h| escape(h, separator)).collect();
+    out.push_str(&line.join(&separator.to_string()));
+    out.push('\n');
+    for row in &rows {
+        let line: Vec<String> = headers
+            .iter()
+            .map(|h| match row.get(*h) {
+                Some(Value::String(s)) => escape(s, separator),
+                Some(Value::Null) | None => String::new(),
+                Some(v) => escape(&v.to_string(), separator),
+            })
+            .collect();
+        out.push_str(&line.join(&separator.to_string()));
+        out.push('\n');
+    }
+    out
+}
+
+/// Quote a CSV field when needed, replace tabs and new lines in a TSV field
+fn escape(value: &str, separator: char) -> String {
+    if separator == '\t' {
+        return value.replace(['\t', '\n', '\r'], " ");
+    }
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Serialize, Default)]
+    struct Item {
+        name: String,
+        status: String,
+    }
+
+    fn items() -> Vec<Item> {
+        vec![
+            Item { name: String::from("ots_kroki"), status: String::from("Up 3 hours") },
+            Item { name: String::from("web"), status: String::from("Exited (0), \"oops\"") },
+        ]
+    }
+
+    #[test]
+    fn test_render_json() {
+        let json = render(&items(), OutputFormat::Json).unwrap();
+        let value: Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value[1]["index"], 2);
+        assert_eq!(value[1]["name"], "web");
+    }
+
+    #[test]
+    fn test_render_csv_and_tsv() {
+        assert_eq!(
+            render(&items(), OutputFormat::Csv).unwrap(),
+            "index,name,status\n1,ots_kroki,Up 3 hours\n2,web,\"Exited (0), \"\"oops\"\"\"\n"
+        );
+        assert_eq!(
+            render(&items(), OutputFormat::Tsv).unwrap(),
+            "index\tname\tstatus\n1\tots_kroki\tUp 3 hours\n2\tweb\tExited (0), \"oops\"\n"
+        );
+        // l'en-tête est écrit même sans élément
+        assert_eq!(render::<Item>(&[], OutputFormat::Csv).unwrap(), "index,name,status\n");
+    }
+
+    #[test]
+    fn test_render_yaml() {
+        let yaml = render(&items(), OutputFormat::Yaml).unwrap();
+        assert!(yaml.starts_with("- index: 1\n  name: ots_kroki\n"));
+        assert_eq!(render(&items(), OutputFormat::Table), None);
+    }
+}
@@ -0,0 +1,181 @@
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::thread;
+use std::time::{Duration, Instant};
+use crate::command_executor::*;
+use crate::config::{Ots, Probe};
+use crate::dkutil::*;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Wait until every service is ready, at most `timeout` for all of them.
+///
+/// A service without probe is ready once its container is running.
+/// Returns 1 when a service is not ready in time or when its container stopped.
+pub fn wait_ready(executor: &dyn CommandExecutor, services: &[Ots], timeout: Duration) -> i32 {
+    let deadline = Instant::now() + timeout;
+    let mut retcode = 0;
+    for ots in services {
+        print_info(&format!("Waiting for {} to be ready", ots.name));
+        loop {
+            match is_ready(executor, ots) {
+                Ok(true) => {
+                    print_info(&format!("{} is ready", ots.name));
+                    break;
+                }
+                Ok(false) => {}
+                Err(e) => {
+                    print_error(&e);
+                    retcode = 1;
+                    break;
+                }
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                print_error(&format!("{} is not ready after {}s", ots.name, timeout.as_secs_f32()));
+                retcode = 1;
+                break;
+            }
+            thread::sleep(POLL_INTERVAL.min(deadline - now));
+        }
+    }
+    retcode
+}
+
+/// Check once whether a service is ready
+pub fn is_ready(executor: &dyn CommandExecutor, ots: &Ots) -> Result<bool, String> {
+    let container = ots.container_name();
+    let format = "{{.State.Status}}|{{if .State.Health}}{{.State.Health.Status}}{{end}}";
+    let output = executor
+        .execute(&["docker", "inspect", "--format", format, &container])
+        .map_err(|_| format!("Container {} not found", container))?;
+    let (state, health) = output.trim().split_once('|').unwrap_or((output.trim(), ""));
+    match state {
+        "running" => {}
+        "created" | "restarting" => return Ok(false),
+        _ => return Err(format!("Container {} is {}", container, state)),
+    }
+
+    Ok(match &ots.ready {
+        None => true,
+        Some(Probe::Tcp { port }) => tcp_ready("localhost", *port),
+        Some(Probe::Http { url, status }) => http_status(url).is_ok_and(|s| s == *status),
+        Some(Probe::Docker) => health == "healthy",
+    })
+}
+
+fn connect(host: &str, port: u16) -> Option<TcpStream> {
+    let addrs: Vec<SocketAddr> = (host, port).to_socket_addrs().ok()?.collect();
+    addrs
+        .iter()
+        .find_map(|addr| TcpStream::connect_timeout(addr, CONNECT_TIMEOUT).ok())
+}
+
+fn tcp_ready(host: &str, port: u16) -> bool {
+    connect(host, port).is_some()
+}
+
+/// Status code of a GET on an `http://` URL
+fn http_status(url: &str) -> Result<u16, String> {
+    let rest = url
+        .strip_prefix("http://")
+        .ok_or_else(|| format!("Unsupported URL {}", url))?;
+    let (authority, path) = match rest.find('/') {
+        Some(i) => (&rest[..i], &rest[i..]),
+        None => (rest, "/"),
+    };
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((host, port)) => (host, port.parse::<u16>().map_err(|_| format!("Invalid URL {}", url))?),
+        None => (authority, 80),
+    };
+
+    let mut stream = connect(host, port).ok_or_else(|| format!("Unable to connect to {}", url))?;
+    stream.set_read_timeout(Some(CONNECT_TIMEOUT)).map_err(|e| e.to_string())?;
+    let request = format!("GET {} HTTP/1.0\r\nHost: {}\r\nConnection: close\r\n\r\n", path, authority);
+    stream.write_all(request.as_bytes()).map_err(|e| e.to_string())?;
+
+    // seule la ligne de statut nous intéresse
+    let mut buffer = [0u8; 64];
+    let mut head = Vec::new();
+    while !head.contains(&b'\n') && head.len() < 256 {
+        let n = stream.read(&mut buffer).map_err(|e| e.to_string())?;
+        if n == 0 {
+            break;
+        }
+        head.extend_from_slice(&buffer[..n]);
+    }
+    let head = String::from_utf8_lossy(&head);
+    head.split_whitespace()
+        .nth(1)
+        .filter(|_| head.starts_with("HTTP/"))
+        .and_then(|code| code.parse().ok())
+        .ok_or_else(|| format!("Invalid HTTP answer from {}", url))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    fn service(ready: Option<Probe>) -> Ots {
+        Ots { name: String::from("web"), image: String::from("nginx"), ready, ..Default::default() }
+    }
+
+    fn running() -> MockCommandExecutor {
+        let mut mock = MockCommandExecutor::new();
+        mock.expect(&["docker", "inspect", "..."], MockResponse::ok("running|\n"));
+        mock
+    }
+
+    #[test]
+    fn test_tcp_probe() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        assert!(is_ready(&running(), &service(Some(Probe::Tcp { port }))).unwrap());
+        drop(listener);
+        assert!(!is_ready(&running(), &service(Some(Probe::Tcp { port }))).unwrap());
+    }
+
+    #[test]
+    fn test_http_probe() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = thread::spawn(move || {
+            for answer in ["HTTP/1.1 503 Service Unavailable\r\n\r\n", "HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok"] {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut request = [0u8; 512];
+                let n = stream.read(&mut request).unwrap();
+                assert!(String::from_utf8_lossy(&request[..n]).starts_with("GET /health HTTP/1.0\r\n"));
+                stream.write_all(answer.as_bytes()).unwrap();
+            }
+        });
+        let probe = Probe::Http { url: format!("http://127.0.0.1:{}/health", port), status: 200 };
+        assert!(!is_ready(&running(), &service(Some(probe.clone()))).unwrap());
+        assert!(is_ready(&running(), &service(Some(probe))).unwrap());
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_docker_probe_and_state() {
+        let mut mock = MockCommandExecutor::new();
+        mock.expect(&["docker", "inspect", "..."], MockResponse::ok("running|starting\n"));
+        assert!(!is_ready(&mock, &service(Some(Probe::Docker))).unwrap());
+        assert!(is_ready(&mock, &service(None)).unwrap());
+
+        let mut mock = MockCommandExecutor::new();
+        mock.expect(&["docker", "inspect", "..."], MockResponse::ok("exited|\n"));
+        assert_eq!(is_ready(&mock, &service(None)), Err(String::from("Container ots_web is exited")));
+    }
+
+    #[test]
+    fn test_wait_timeout() {
+        let mut mock = MockCommandExecutor::new();
+        mock.expect(&["docker", "inspect", "..."], MockResponse::ok("running|unhealthy\n"));
+        let start = Instant::now();
+        assert_eq!(wait_ready(&mock, &[service(Some(Probe::Docker))], Duration::from_millis(200)), 1);
+        assert!(start.elapsed() < Duration::from_secs(2));
+        assert_eq!(wait_ready(&running(), &[service(None)], Duration::from_millis(200)), 0);
+    }
+}
@@ -1,54 +1,57 @@
 
 use colored::*;
+use crate::cli::{self, SysCommand};
+use crate::command_executor::*;
 use crate::container_helper;
 use crate::dkutil::*;
 use crate::volume_helper;
 use crate::image_helper;
+use crate::output::OutputFormat;
 
 
 pub fn usage() {
-    println!("{}", "SYSTEM:".cyan());
-    print_colored("(y) . dk sys show         (w): Show extended information");
-    print_colored("(y) . dk sys prune        (w): Delete unused data (containers, images, volumes, build cache)");
-    print_colored("(y) . dk sys size         (w): Show data size (docker system df)");
-   
+    cli::print_usage_section("SYSTEM");
 }
 
-pub fn cmd(arguments: &[String]) ->i32 {
-    if arguments.is_empty() {
-        show();
-        return 0;
+pub fn cmd(executor: &dyn CommandExecutor, command: Option<SysCommand>) -> i32 {
+    match command {
+        None | Some(SysCommand::Show) => {
+            show(executor);
+            0
+        }
+        Some(SysCommand::Prune) => prune(executor),
+        Some(SysCommand::Size) => size(executor),
     }
-    let command = &arguments[0];
-    match command.as_str() {
-        "show" => show(),
-        "prune" => prune(),
-        "size" => size(),
-        _ => print_error("unknown command"),
-    }
-    return 0;
-
 }
 
-pub fn show() {
+pub fn show(executor: &dyn CommandExecutor) {
     println!("{}", "VOLUMES:".cyan());
-    volume_helper::show();
+    volume_helper::show(executor, OutputFormat::Table);
     println!();
     println!("{}", "IMAGES:".cyan());
-    image_helper::show();
+    image_helper::show(executor, OutputFormat::Table);
     println!();
     println!("{}", "CONTAINERS:".cyan());    
-    container_helper::show();
+    container_helper::show(executor, OutputFormat::Table);
 }
 
-pub fn prune() {
+/// Prune the networks, the volumes and the build cache, every step runs even if one fails
+pub fn prune(executor: &dyn CommandExecutor) -> i32 {
+    let mut retcode = 0;
     print_info("Pruning networks");
-    print_and_run(&["docker", "network", "prune", "-f"]);
+    if print_and_run(executor, &["docker", "network", "prune", "-f"]) != 0 {
+        retcode = 1;
+    }
     print_info("Pruning volumes");
-    print_and_run(&["docker", "volume", "prune", "-f"]);
-    print_and_run(&["docker", "buildx", "prune", "-f"]);
+    if print_and_run(executor, &["docker", "volume", "prune", "-f"]) != 0 {
+        retcode = 1;
+    }
+    if print_and_run(executor, &["docker", "buildx", "prune", "-f"]) != 0 {
+        retcode = 1;
+    }
+    retcode
 }
 
-pub fn size() {
-    print_and_run(&["docker", "system", "df"]);
+pub fn size(executor: &dyn CommandExecutor) -> i32 {
+    print_and_run(executor, &["docker", "system", "df"])
 }
@@ -0,0 +1,207 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::Path;
+
+const ENV_FILE_NAME: &str = ".env";
+pub const SECRETS_FILE_NAME: &str = "secrets.env";
+const MASK: &str = "******";
+/// Shown instead of the value of a variable that is not set
+pub const UNSET: &str = "<unset>";
+
+/// Variables available to the `${VAR}` templates of the configuration.
+///
+/// The environment comes first, then the secrets file, then the `.env` file,
+/// both read from the configuration directory.
+#[derive(Debug, Default, Clone)]
+pub struct Variables {
+    values: BTreeMap<String, String>,
+    secrets: BTreeMap<String, String>,
+}
+
+impl Variables {
+    /// Read `.env` and `secrets.env` from `dir`, a missing file is not an error.
+    /// The secrets file must not be readable by the group or the others.
+    pub fn load(dir: &Path) -> Result<Variables, String> {
+        let mut variables = Variables::default();
+        let env_file = dir.join(ENV_FILE_NAME);
+        if env_file.exists() {
+            variables.values = read_env_file(&env_file)?;
+        }
+        let secrets_file = dir.join(SECRETS_FILE_NAME);
+        if secrets_file.exists() {
+            check_permissions(&secrets_file)?;
+            variables.secrets = read_env_file(&secrets_file)?;
+        }
+        Ok(variables)
+    }
+
+    pub fn with_values(values: BTreeMap<String, String>, secrets: BTreeMap<String, String>) -> Variables {
+        Variables { values, secrets }
+    }
+
+    pub fn get(&self, name: &str) -> Option<String> {
+        env::var(name)
+            .ok()
+            .or_else(|| self.secrets.get(name).cloned())
+            .or_else(|| self.values.get(name).cloned())
+    }
+
+    /// Value of a variable, masked when it is a secret
+    pub fn get_masked(&self, name: &str) -> Option<String> {
+        if self.is_secret(name) {
+            Some(String::from(MASK))
+        } else {
+            self.get(name)
+        }
+    }
+
+    pub fn is_secret(&self, name: &str) -> bool {
+        self.secrets.contains_key(name)
+    }
+}
+
+/// Replace `${VAR}` and `${VAR:-default}` in `text` (`$$` gives a `$`).
+///
+/// `lookup` gives the value of a variable, a variable without value nor
+/// default is an error.
+pub fn interpolate(text: &str, lookup: &dyn Fn(&str) -> Option<String>) -> Result<String, String> {
+    interpolate_or(text, lookup, None)
+}
+
+/// Like `interpolate`, a variable without value nor default gives `unset` when it is set
+pub fn interpolate_or(text: &str, lookup: &dyn Fn(&str) -> Option<String>, unset: Option<&str>) -> Result<String, String> {
+    let mut result = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('$') {
+        result.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        if let Some(after) = after.strip_prefix('$') {
+            result.push('$');
+            rest = after;
+            continue;
+        }
+        let Some(body) = after.strip_prefix('{') else {
+            result.push('$');
+            rest = after;
+            continue;
+        };
+        let end = body
+            .find('}')
+            .ok_or_else(|| format!("Unterminated variable in '{}'", text))?;
+        let (name, default) = match body[..end].split_once(":-") {
+            Some((name, default)) => (name, Some(default)),
+            None => (&body[..end], None),
+        };
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Err(format!("Invalid variable name '{}' in '{}'", name, text));
+        }
+        let value = lookup(name)
+            .filter(|v| !v.is_empty() || default.is_none())
+            .or(default.map(String::from))
+            .or(unset.map(String::from))
+            .ok_or_else(|| format!("Variable {} is not set (environment, .env or secrets.env)", name))?;
+        result.push_str(&value);
+        rest = &body[end + 1..];
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
+/// Read a `KEY=value` file (`#` comments, optional `export` and quotes)
+fn read_env_file(path: &Path) -> Result<BTreeMap<String, String>, String> {
+    let content = fs::read_to_string(path).map_err(|e| format!("Unable to read {} : {}", path.display(), e))?;
+    let mut values = BTreeMap::new();
+    for (number, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| format!("{}:{}: expected KEY=value", path.display(), number + 1))?;
+        let value = value.trim();
+        let value = [('"', '"'), ('\'', '\'')]
+            .iter()
+            .find_map(|(open, close)| value.strip_prefix(*open)?.strip_suffix(*close))
+            .unwrap_or(value);
+        values.insert(key.trim().to_string(), value.to_string());
+    }
+    Ok(values)
+}
+
+#[cfg(unix)]
+fn check_permissions(path: &Path) -> Result<(), String> {
+    use std::os::unix::fs::PermissionsExt;
+    let mode = fs::metadata(path)
+        .map_err(|e| format!("Unable to read {} : {}", path.display(), e))?
+        .permissions()
+        .mode();
+    if mode & 0o077 != 0 {
+        return Err(format!(
+            "{} is readable by other users (mode {:o}), run: chmod 600 {}",
+            path.display(),
+            mode & 0o777,
+            path.display()
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn check_permissions(_path: &Path) -> Result<(), String> {
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn variables() -> Variables {
+        Variables::with_values(
+            BTreeMap::from([(String::from("DK_TEST_PORT"), String::from("8080")), (String::from("DK_TEST_EMPTY"), String::new())]),
+            BTreeMap::from([(String::from("DK_TEST_PASSWORD"), String::from("s3cr€t"))]),
+        )
+    }
+
+    #[test]
+    fn test_interpolate() {
+        let vars = variables();
+        let get = |name: &str| vars.get(name);
+        assert_eq!(interpolate("${DK_TEST_PORT}:80", &get).unwrap(), "8080:80");
+        assert_eq!(interpolate("${DK_TEST_UNSET:-9000}:80", &get).unwrap(), "9000:80");
+        assert_eq!(interpolate("${DK_TEST_EMPTY:-x}", &get).unwrap(), "x");
+        assert_eq!(interpolate("${DK_TEST_EMPTY}", &get).unwrap(), "");
+        assert_eq!(interpolate("p=$$HOME $1 ${DK_TEST_PASSWORD}", &get).unwrap(), "p=$HOME $1 s3cr€t");
+        assert_eq!(interpolate("pwd=${DK_TEST_PASSWORD}", &|n| vars.get_masked(n)).unwrap(), "pwd=******");
+        assert_eq!(interpolate("${DK_TEST_UNSET}", &get), Err(String::from("Variable DK_TEST_UNSET is not set (environment, .env or secrets.env)")));
+        assert!(interpolate("${DK_TEST_PORT", &get).is_err());
+        assert!(interpolate("${DK TEST}", &get).is_err());
+        assert_eq!(interpolate_or("${DK_TEST_UNSET}/${DK_TEST_UNSET:-x}", &get, Some(UNSET)).unwrap(), "<unset>/x");
+    }
+
+    #[test]
+    fn test_load_files() {
+        let dir = env::temp_dir().join(format!("dk_variables_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join(".env"), "# ports\nexport DK_TEST_WEB_PORT=8081\nDK_TEST_TITLE=\"My web\"\n").unwrap();
+        let secrets = dir.join(SECRETS_FILE_NAME);
+        fs::write(&secrets, "DK_TEST_TOKEN='abc=def'\n").unwrap();
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&secrets, fs::Permissions::from_mode(0o644)).unwrap();
+            assert!(Variables::load(&dir).unwrap_err().contains("chmod 600"));
+            fs::set_permissions(&secrets, fs::Permissions::from_mode(0o600)).unwrap();
+        }
+        let vars = Variables::load(&dir).unwrap();
+        assert_eq!(vars.get("DK_TEST_WEB_PORT").as_deref(), Some("8081"));
+        assert_eq!(vars.get("DK_TEST_TITLE").as_deref(), Some("My web"));
+        assert_eq!(vars.get("DK_TEST_TOKEN").as_deref(), Some("abc=def"));
+        assert!(vars.is_secret("DK_TEST_TOKEN"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,76 +1,71 @@
-use colored::*;
-use std::process::Command;
+use crate::cli::{self, VolCommand};
+use crate::command_executor::*;
 use crate::dkutil::*;
+use crate::models::*;
+use crate::output::*;
 
 
 pub fn usage() {
-    
-    println!("{}", "VOLUMES:".cyan());
-    print_colored("(y) . dk vol              (w): Show state of the containers");
-    print_colored("(y) . dk vol prune        (w): Delete all unused volumes");
-    print_colored("(y) . dk vol rm (b)<volume*> (w): Delete specified volumes");
+    cli::print_usage_section("VOLUMES");
 }
 
-pub fn get_volumes() -> Vec<Vec<String>> {
-    let output = Command::new("docker")
-        .args(&["volume", "ls", "--format", "{{.Name}}"])
-        .output()
-        .expect("Échec de 'docker volume ls'");
-    let stdout = String::from_utf8_lossy(&output.stdout);
-    let mut table = Vec::new();
-    let mut index = 1;
-    for line in stdout.lines() {
-        table.push(vec![index.to_string(), line.to_string()]);
-        index += 1;
+/// The volumes; an error when docker cannot list them
+pub fn get_volumes(executor: &dyn CommandExecutor) -> Result<Vec<Volume>, String> {
+    if let Some(engine) = executor.engine() {
+        return engine.volumes().map(|volumes| volumes.into_iter().map(Volume::from).collect());
     }
-    table
+
+    let stdout = capture(executor, &["docker", "volume", "ls", "--format", "{{json .}}"])?;
+    parse_json_lines(&stdout)
 }
 
-pub fn show() {
-    let volumes = get_volumes();
+pub fn show(executor: &dyn CommandExecutor, format: OutputFormat) -> i32 {
+    let volumes = match get_volumes(executor) {
+        Ok(volumes) => volumes,
+        Err(e) => {
+            eprint_error(&e);
+            return 1;
+        }
+    };
+    if let Some(out) = render(&volumes, format) {
+        print!("{}", out);
+        return 0;
+    }
     println!("{:<5} {:<20}", "Index", "Volume Name");
-    for row in volumes {
-        println!("{:<5} {:<20}", row[0], row[1]);
+    for (index, v) in volumes.iter().enumerate() {
+        println!("{:<5} {:<20}", index + 1, v.name);
     }
+    0
 }
 
-pub fn cmd(arguments: &[String]) {
-    if arguments.is_empty() {
-        show();
-        return;
-    }
-    let command = &arguments[0];
-    match command.as_str() {
-        "rm" => {
-            if arguments.len() < 2 {
-                println!("Error: 'rm' command requires at least one volume");
-                return;
-            }
-            rm(&arguments[1..]);
+pub fn cmd(executor: &dyn CommandExecutor, output: OutputFormat, command: Option<VolCommand>) -> i32 {
+    match command {
+        None => show(executor, output),
+        Some(VolCommand::Prune) => {
+            print_info("Pruning volumes");
+            print_and_run(executor, &["docker", "volume", "prune", "-f"])
         }
-        _ => print_error("unknown command"),
+        Some(VolCommand::Rm { volumes }) => rm(executor, &volumes),
     }
 }
 
-fn rm(filters: &[String]) {
-    let volumes = translate_to_id(filters);
+fn rm(executor: &dyn CommandExecutor, filters: &[String]) -> i32 {
+    let volumes = translate_to_id(executor, filters);
+    let mut retcode = 0;
     for v in volumes {
         print_info(&format!("Removing volume {}", v));
-        print_and_run(&["docker", "volume", "rm", &v]);
+        if print_and_run(executor, &["docker", "volume", "rm", &v]) != 0 {
+            retcode = 1;
+        }
     }
+    retcode
 }
 
 
-fn translate_to_id(filters: &[String]) -> Vec<String> {
-    let volumes = get_volumes();
-    let max_rank = volumes.iter().count();
-    
-    filters.iter().map(|f| {
-        if is_valid_rank(f, max_rank) {
-            let row = &volumes[f.parse::<usize>().unwrap() - 1];
-            row[1].clone()
-        } else {
-            f.to_string()
-        }
-    }).collect()
-}
\ No newline at end of file
+fn translate_to_id(executor: &dyn CommandExecutor, filters: &[String]) -> Vec<String> {
+    let volumes = get_volumes(executor).unwrap_or_else(|e| {
+        print_error(&e);
+        Vec::new()
+    });
+    translate_ranks(&volumes, filters, |v| v.name.clone())
+}